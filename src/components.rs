@@ -0,0 +1,515 @@
+use crate::input::InputSource;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+// /* Enums
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
+pub enum Direction {
+    UP,
+    DOWN,
+    LEFT,
+    RIGHT,
+    NONE,
+}
+impl Direction {
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::UP => Direction::DOWN,
+            Direction::DOWN => Direction::UP,
+            Direction::LEFT => Direction::RIGHT,
+            Direction::RIGHT => Direction::LEFT,
+            Direction::NONE => Direction::NONE,
+        }
+    }
+    // One cell's step this way, y going up the board. Standing still is no step at all.
+    pub fn velocity(&self) -> Vec2 {
+        match self {
+            Direction::UP => Vec2::new(0., 1.),
+            Direction::DOWN => Vec2::new(0., -1.),
+            Direction::LEFT => Vec2::new(-1., 0.),
+            Direction::RIGHT => Vec2::new(1., 0.),
+            Direction::NONE => Vec2::ZERO,
+        }
+    }
+}
+// */ Enums
+
+// /*Game Constants
+pub const INPUT_QUEUE_SIZE: usize = 3;
+pub const FOOD_START_VALUE: f32 = 10.;
+pub const FOOD_MIN_VALUE: f32 = 1.;
+pub const FOOD_VALUE_DECAY: f32 = 0.25;
+pub const GOLDEN_FOOD_TICKS: u32 = 20;
+pub const GOLDEN_FOOD_BONUS: u32 = 20;
+pub const GOLDEN_FOOD_GROWTH: u32 = 2;
+pub const ROTTEN_FOOD_TICKS: u32 = 30;
+pub const ROTTEN_FOOD_SHRINK: usize = 2;
+// */Game Constants
+
+// /*Components
+#[derive(Component)]
+pub struct Velocity {
+    pub direction: Direction,
+}
+#[derive(Component, Default)]
+pub struct NextDirection {
+    pub queue: VecDeque<Direction>,
+}
+impl NextDirection {
+    pub fn new() -> Self {
+        NextDirection {
+            queue: VecDeque::new(),
+        }
+    }
+    // Queues a turn to be applied on a later tick. Repeats of the last queued
+    // direction are dropped so a held key doesn't flood the queue, and so is a turn
+    // straight back from it: each turn is applied after the one before it. NONE is never
+    // queued, so once a snake moves it can't stop and lose the way it was going; `applied`
+    // stays a real direction for the guard across a pause or a countdown.
+    pub fn push(&mut self, direction: Direction, applied: Direction) {
+        let last_queued = self.queue.back().copied().unwrap_or(applied);
+        if direction != Direction::NONE
+            && direction != last_queued
+            && direction != last_queued.opposite()
+            && self.queue.len() < INPUT_QUEUE_SIZE
+        {
+            self.queue.push_back(direction);
+        }
+    }
+    // Whether `direction` after the last queued turn heads straight back the way the snake
+    // was going before that turn. Two quick turns like that bring the head back alongside
+    // its neck within two ticks.
+    pub fn doubles_back(&self, direction: Direction, applied: Direction) -> bool {
+        let before_last = match self.queue.len() {
+            0 => return false,
+            1 => applied,
+            len => self.queue[len - 2],
+        };
+        before_last != Direction::NONE && direction == before_last.opposite()
+    }
+}
+// The cell a segment, food or wall is on, (0, 0) being the bottom left of the board.
+// Every rule works on it; the Transform is only set from it for drawing.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GridPos {
+    pub x: i32,
+    pub y: i32,
+}
+impl GridPos {
+    pub fn new(x: i32, y: i32) -> Self {
+        GridPos { x, y }
+    }
+    pub fn cell(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+}
+impl From<(i32, i32)> for GridPos {
+    fn from((x, y): (i32, i32)) -> Self {
+        GridPos { x, y }
+    }
+}
+// Which player a snake belongs to, counted from 0. Every segment carries it.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Player(pub u8);
+#[derive(Component)]
+pub struct Head;
+// The segments of one snake, head first. Kept on the head, so every snake on the board
+// carries its own body.
+#[derive(Component)]
+pub struct Snake {
+    pub segments: Vec<Entity>,
+}
+// Growth still owed to a snake, kept on its head. Segments are added one per tick as
+// the tail moves off `cell`.
+#[derive(Component)]
+pub struct LateSpawn {
+    pub cell: GridPos,
+    pub pending: u32,
+    pub wait: bool,
+}
+impl LateSpawn {
+    pub fn new() -> Self {
+        LateSpawn {
+            cell: GridPos::default(),
+            pending: 0,
+            wait: true,
+        }
+    }
+}
+impl Default for LateSpawn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[derive(Component)]
+pub struct Tail;
+#[derive(Component)]
+pub struct Food;
+#[derive(Component, Default)]
+pub struct ValueTimer {
+    pub ticks: u32,
+}
+impl ValueTimer {
+    pub fn new() -> Self {
+        ValueTimer { ticks: 0 }
+    }
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+    }
+    pub fn reset(&mut self) {
+        self.ticks = 0;
+    }
+    // Current worth of the food, linearly decaying per tick down to FOOD_MIN_VALUE
+    pub fn value(&self) -> f32 {
+        (FOOD_START_VALUE - self.ticks as f32 * FOOD_VALUE_DECAY).max(FOOD_MIN_VALUE)
+    }
+}
+// Where a snake segment was drawn on the previous tick and where it is now. Only drawing
+// uses it, the rules go by GridPos.
+#[derive(Component)]
+pub struct Motion {
+    pub from: Vec3,
+    pub to: Vec3,
+}
+impl Motion {
+    pub fn new(translation: Vec3) -> Self {
+        Motion {
+            from: translation,
+            to: translation,
+        }
+    }
+    // The drawn position `fraction` of the way through the step. A move longer than
+    // `max_step`, like wrapping to the other edge, snaps instead of sliding across.
+    pub fn interpolate(&self, fraction: f32, max_step: f32) -> Vec3 {
+        if self.from.distance(self.to) > max_step {
+            self.to
+        } else {
+            self.from.lerp(self.to, fraction.clamp(0., 1.))
+        }
+    }
+}
+// The camera the playfield is drawn with
+#[derive(Component)]
+pub struct GameCamera;
+// One of the eyes drawn on the head
+#[derive(Component)]
+pub struct Eye;
+// The bar drawn from a body segment towards the segment in front of it. Corners need
+// nothing more, the square of the segment in the bend fills them.
+#[derive(Component)]
+pub struct Joint;
+// One cell of the background checkerboard
+#[derive(Component)]
+pub struct GridTile;
+// An obstacle tile, running into one ends the round
+#[derive(Component)]
+pub struct Wall;
+// One of the four strips drawn around the board, marking where the snake leaves it
+#[derive(Component)]
+pub struct Border;
+// One end of a portal pair. A head moving onto it comes out on the `partner` end.
+#[derive(Component)]
+pub struct Portal {
+    pub partner: Entity,
+}
+// A bar along one side of the board, lit in wrap mode while a head is about to cross
+// that side. `side` is the way a head leaves the board through it.
+#[derive(Component)]
+pub struct EdgeGlow {
+    pub side: Direction,
+}
+// Marks a Food as the rare golden kind, worth a bonus and two segments
+#[derive(Component)]
+pub struct Golden;
+// Marks a Food as rotten. It's never eaten for points: running onto it ends the round,
+// or in truncate mode costs the end of the tail.
+#[derive(Component)]
+pub struct Rotten;
+// Ticks left before a temporary entity, like golden or rotten food, goes away
+#[derive(Component)]
+pub struct Lifetime {
+    pub ticks_left: u32,
+}
+impl Lifetime {
+    pub fn new(ticks: u32) -> Self {
+        Lifetime { ticks_left: ticks }
+    }
+    pub fn tick(&mut self) {
+        self.ticks_left = self.ticks_left.saturating_sub(1);
+    }
+    pub fn expired(&self) -> bool {
+        self.ticks_left == 0
+    }
+}
+// A sprite that shrinks and fades away over its timer, then is despawned
+#[derive(Component)]
+pub struct FadeEffect {
+    pub timer: Timer,
+}
+impl FadeEffect {
+    pub fn new(seconds: f32) -> Self {
+        FadeEffect {
+            timer: Timer::from_seconds(seconds, false),
+        }
+    }
+    // From 1 when spawned down to 0 when it's gone
+    pub fn remaining(&self) -> f32 {
+        self.timer.percent_left()
+    }
+}
+// Lights a head up for a moment after it eats, fading back over the timer
+#[derive(Component)]
+pub struct EatFlash {
+    pub timer: Timer,
+}
+impl EatFlash {
+    pub fn new(seconds: f32) -> Self {
+        EatFlash {
+            timer: Timer::from_seconds(seconds, false),
+        }
+    }
+    // From 1 right after eating down to 0 when the head has its own color back
+    pub fn remaining(&self) -> f32 {
+        self.timer.percent_left()
+    }
+}
+// Scales a freshly grown body segment up from nothing over the timer. Only the sprite
+// grows in, the segment takes up its cell from the tick it's added.
+#[derive(Component)]
+pub struct GrowIn {
+    pub timer: Timer,
+}
+impl GrowIn {
+    pub fn new(seconds: f32) -> Self {
+        GrowIn {
+            timer: Timer::from_seconds(seconds, false),
+        }
+    }
+    // From 0 when spawned up to 1 at full size
+    pub fn progress(&self) -> f32 {
+        self.timer.percent()
+    }
+}
+// The text of a toast, despawned with the node centering it once its timer runs out
+#[derive(Component)]
+pub struct ToastText {
+    pub timer: Timer,
+}
+impl ToastText {
+    pub fn new(seconds: f32) -> Self {
+        ToastText {
+            timer: Timer::from_seconds(seconds, false),
+        }
+    }
+    // Fully there until the last `fade_seconds`, then down to 0 when it's gone
+    pub fn opacity(&self, fade_seconds: f32) -> f32 {
+        let left = self.timer.duration().saturating_sub(self.timer.elapsed());
+        (left.as_secs_f32() / fade_seconds).min(1.)
+    }
+}
+#[derive(Component)]
+pub struct ScoreText;
+#[derive(Component)]
+pub struct PauseText;
+#[derive(Component)]
+pub struct VictoryText;
+#[derive(Component)]
+pub struct DiagnosticsText;
+#[derive(Component)]
+pub struct CellOverlayText;
+#[derive(Component)]
+pub struct SplashText;
+#[derive(Component)]
+pub struct MenuText;
+#[derive(Component)]
+pub struct PlayerCountText;
+#[derive(Component)]
+pub struct OptionsText;
+#[derive(Component)]
+pub struct DifficultyText;
+#[derive(Component)]
+pub struct ModeText;
+#[derive(Component)]
+pub struct Controller {
+    pub source: Box<dyn InputSource + Send + Sync>,
+}
+// */Components
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn directions_step_one_cell_along_an_axis() {
+        assert_eq!(Direction::UP.velocity(), Vec2::new(0., 1.));
+        assert_eq!(Direction::DOWN.velocity(), Vec2::new(0., -1.));
+        assert_eq!(Direction::LEFT.velocity(), Vec2::new(-1., 0.));
+        assert_eq!(Direction::RIGHT.velocity(), Vec2::new(1., 0.));
+        assert_eq!(Direction::NONE.velocity(), Vec2::ZERO);
+        for direction in [
+            Direction::UP,
+            Direction::DOWN,
+            Direction::LEFT,
+            Direction::RIGHT,
+        ] {
+            assert_eq!(direction.velocity().length(), 1.);
+            assert_eq!(direction.opposite().velocity(), -direction.velocity());
+        }
+    }
+
+    #[test]
+    fn next_direction_queues_turns_between_ticks() {
+        let mut next_direction = NextDirection::new();
+        next_direction.push(Direction::UP, Direction::RIGHT);
+        // A held key keeps reporting the same direction every frame
+        next_direction.push(Direction::UP, Direction::RIGHT);
+        next_direction.push(Direction::RIGHT, Direction::RIGHT);
+
+        assert_eq!(next_direction.queue.pop_front(), Some(Direction::UP));
+        assert_eq!(next_direction.queue.pop_front(), Some(Direction::RIGHT));
+        assert_eq!(next_direction.queue.pop_front(), None);
+    }
+
+    #[test]
+    fn next_direction_rejects_reversal_and_overflow() {
+        let mut next_direction = NextDirection::new();
+        next_direction.push(Direction::LEFT, Direction::RIGHT);
+        assert!(next_direction.queue.is_empty());
+
+        for direction in [
+            Direction::UP,
+            Direction::RIGHT,
+            Direction::DOWN,
+            Direction::RIGHT,
+        ] {
+            next_direction.push(direction, Direction::NONE);
+        }
+        assert_eq!(next_direction.queue.len(), INPUT_QUEUE_SIZE);
+    }
+
+    #[test]
+    fn next_direction_never_queues_a_stop() {
+        let mut next_direction = NextDirection::new();
+        next_direction.push(Direction::NONE, Direction::RIGHT);
+        next_direction.push(Direction::NONE, Direction::NONE);
+        assert!(next_direction.queue.is_empty());
+
+        // Still compared against the last real turn after a stop was offered
+        next_direction.push(Direction::UP, Direction::RIGHT);
+        next_direction.push(Direction::NONE, Direction::RIGHT);
+        next_direction.push(Direction::DOWN, Direction::RIGHT);
+        assert_eq!(next_direction.queue, VecDeque::from([Direction::UP]));
+    }
+
+    #[test]
+    fn next_direction_spots_doubling_back_over_two_ticks() {
+        let mut next_direction = NextDirection::new();
+        // Nothing queued, a single turn can't double back
+        assert!(!next_direction.doubles_back(Direction::UP, Direction::RIGHT));
+
+        // Moving right, up and then left takes the head back next to its neck
+        next_direction.push(Direction::UP, Direction::RIGHT);
+        assert!(next_direction.doubles_back(Direction::LEFT, Direction::RIGHT));
+        assert!(!next_direction.doubles_back(Direction::RIGHT, Direction::RIGHT));
+
+        // Up, right, then down looks two turns back past the applied direction
+        next_direction.push(Direction::RIGHT, Direction::RIGHT);
+        assert!(next_direction.doubles_back(Direction::DOWN, Direction::RIGHT));
+        assert!(!next_direction.doubles_back(Direction::UP, Direction::RIGHT));
+
+        // A snake standing still has no way it was going
+        let mut standing = NextDirection::new();
+        standing.push(Direction::UP, Direction::NONE);
+        assert!(!standing.doubles_back(Direction::LEFT, Direction::NONE));
+    }
+
+    #[test]
+    fn next_direction_rejects_reversal_of_a_queued_turn() {
+        // Moving right, up then down within one step would reverse once up is applied
+        let mut next_direction = NextDirection::new();
+        next_direction.push(Direction::UP, Direction::RIGHT);
+        next_direction.push(Direction::DOWN, Direction::RIGHT);
+        next_direction.push(Direction::LEFT, Direction::RIGHT);
+        assert_eq!(
+            next_direction.queue,
+            VecDeque::from([Direction::UP, Direction::LEFT])
+        );
+
+        let mut previous = Direction::RIGHT;
+        for direction in next_direction.queue {
+            assert_ne!(direction, previous.opposite());
+            previous = direction;
+        }
+    }
+
+    #[test]
+    fn fade_effect_runs_down_to_nothing() {
+        let mut fade = FadeEffect::new(0.3);
+        assert_eq!(fade.remaining(), 1.);
+        fade.timer.tick(Duration::from_secs_f32(0.15));
+        assert!((fade.remaining() - 0.5).abs() < 1e-4);
+        fade.timer.tick(Duration::from_secs_f32(0.2));
+        assert_eq!(fade.remaining(), 0.);
+        assert!(fade.timer.finished());
+    }
+
+    #[test]
+    fn eat_flash_fades_back_over_its_timer() {
+        let mut flash = EatFlash::new(0.2);
+        assert_eq!(flash.remaining(), 1.);
+        flash.timer.tick(Duration::from_secs_f32(0.1));
+        assert!((flash.remaining() - 0.5).abs() < 1e-6);
+        flash.timer.tick(Duration::from_secs_f32(0.2));
+        assert!(flash.timer.finished());
+        assert_eq!(flash.remaining(), 0.);
+    }
+
+    #[test]
+    fn food_value_decreases_over_ticks() {
+        let mut value_timer = ValueTimer::new();
+        let mut last_value = value_timer.value();
+        assert_eq!(last_value, FOOD_START_VALUE);
+
+        for _ in 0..10 {
+            value_timer.tick();
+            assert!(value_timer.value() < last_value);
+            last_value = value_timer.value();
+        }
+    }
+
+    #[test]
+    fn food_value_is_floored_and_resets() {
+        let mut value_timer = ValueTimer::new();
+        for _ in 0..1000 {
+            value_timer.tick();
+        }
+        assert_eq!(value_timer.value(), FOOD_MIN_VALUE);
+
+        value_timer.reset();
+        assert_eq!(value_timer.value(), FOOD_START_VALUE);
+    }
+
+    #[test]
+    fn lifetime_expires_after_its_ticks() {
+        let mut lifetime = Lifetime::new(2);
+        lifetime.tick();
+        assert!(!lifetime.expired());
+        lifetime.tick();
+        assert!(lifetime.expired());
+        lifetime.tick();
+        assert_eq!(lifetime.ticks_left, 0);
+    }
+
+    #[test]
+    fn motion_slides_one_cell_and_snaps_on_jumps() {
+        let mut motion = Motion::new(Vec3::ZERO);
+        motion.to = Vec3::new(50., 0., 0.);
+        assert_eq!(motion.interpolate(0., 50.), Vec3::ZERO);
+        assert_eq!(motion.interpolate(0.5, 50.), Vec3::new(25., 0., 0.));
+        assert_eq!(motion.interpolate(2., 50.), Vec3::new(50., 0., 0.));
+
+        motion.to = Vec3::new(-750., 0., 0.);
+        assert_eq!(motion.interpolate(0.5, 50.), Vec3::new(-750., 0., 0.));
+    }
+}
@@ -0,0 +1,53 @@
+use crate::components::Player;
+
+// A snake's head landed on a food at `cell`. `points` is what the food was worth, bonus
+// included, before the combo multiplier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoodEaten {
+    pub player: Player,
+    pub cell: (i32, i32),
+    pub points: u32,
+    pub golden: bool,
+}
+
+// The round ended, won or lost. `crashed` is a loss to a wall, the board edge or a snake,
+// not to rotten food or the clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameOver {
+    pub won: bool,
+    pub crashed: bool,
+}
+
+// Where on the screen a toast shows up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastPosition {
+    Center,
+    Top,
+}
+
+// A short message shown on screen for `seconds`, then faded out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Toast {
+    pub text: String,
+    pub seconds: f32,
+    pub position: ToastPosition,
+}
+impl Toast {
+    pub fn center(text: &str, seconds: f32) -> Self {
+        Toast {
+            text: text.to_string(),
+            seconds,
+            position: ToastPosition::Center,
+        }
+    }
+    pub fn top(text: &str, seconds: f32) -> Self {
+        Toast {
+            position: ToastPosition::Top,
+            ..Toast::center(text, seconds)
+        }
+    }
+}
+
+// A finished round is being played again from the start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameReset;
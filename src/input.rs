@@ -0,0 +1,440 @@
+use crate::components::{Direction, GridPos};
+use crate::resources::{InputMode, KeyBindings};
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+// How far the left stick must be pushed before it steers, so a stick at rest that
+// drifts a little doesn't turn the snake
+pub const STICK_DEAD_ZONE: f32 = 0.5;
+
+// /*Input sources
+pub struct InputContext<'a> {
+    pub keyboard: &'a Input<KeyCode>,
+    pub gamepad_buttons: &'a Input<GamepadButton>,
+    pub gamepad_axes: &'a Axis<GamepadAxis>,
+    // The first connected gamepad, if any
+    pub gamepad: Option<Gamepad>,
+    pub input_mode: InputMode,
+    pub tick: bool,
+    pub head: GridPos,
+    pub food: Option<GridPos>,
+    // The way the snake is moving, NONE before its first move
+    pub heading: Direction,
+    // Whether the head can move one cell that way without crashing into anything
+    pub is_safe: &'a dyn Fn(Direction) -> bool,
+}
+// Anything that can steer a snake. Sources only report the wanted direction,
+// the reversal guard is applied by NextDirection::push for every source alike.
+pub trait InputSource {
+    fn next_direction(&mut self, context: &InputContext) -> Option<Direction>;
+}
+impl InputContext<'_> {
+    // Whether any of `keys` steers this frame under the input mode
+    pub fn keys_active(&self, keys: impl IntoIterator<Item = KeyCode>) -> bool {
+        match self.input_mode {
+            InputMode::Held => self.keyboard.any_pressed(keys),
+            InputMode::Tapped => self.keyboard.any_just_pressed(keys),
+        }
+    }
+    pub fn button_active(&self, button: GamepadButton) -> bool {
+        match self.input_mode {
+            InputMode::Held => self.gamepad_buttons.pressed(button),
+            InputMode::Tapped => self.gamepad_buttons.just_pressed(button),
+        }
+    }
+}
+pub struct KeyboardSource {
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub up: Vec<KeyCode>,
+    pub down: Vec<KeyCode>,
+}
+impl KeyboardSource {
+    // Both players' keys steer the same way, for a single player
+    pub fn any(bindings: &KeyBindings) -> Self {
+        KeyboardSource {
+            left: bindings.left.to_vec(),
+            right: bindings.right.to_vec(),
+            up: bindings.up.to_vec(),
+            down: bindings.down.to_vec(),
+        }
+    }
+    // One player's half of the keyboard, 0 for the first player
+    pub fn player(bindings: &KeyBindings, index: usize) -> Self {
+        KeyboardSource {
+            left: vec![bindings.left[index]],
+            right: vec![bindings.right[index]],
+            up: vec![bindings.up[index]],
+            down: vec![bindings.down[index]],
+        }
+    }
+}
+impl InputSource for KeyboardSource {
+    fn next_direction(&mut self, context: &InputContext) -> Option<Direction> {
+        if context.keys_active(self.left.iter().copied()) {
+            Some(Direction::LEFT)
+        } else if context.keys_active(self.right.iter().copied()) {
+            Some(Direction::RIGHT)
+        } else if context.keys_active(self.up.iter().copied()) {
+            Some(Direction::UP)
+        } else if context.keys_active(self.down.iter().copied()) {
+            Some(Direction::DOWN)
+        } else {
+            None
+        }
+    }
+}
+// The D-pad, or else the left stick along whichever axis it's pushed furthest
+#[derive(Default)]
+pub struct GamepadSource {
+    // Where the stick pointed last frame. Tapped mode only counts a fresh push, like
+    // a button's just_pressed.
+    stick: Option<Direction>,
+}
+impl GamepadSource {
+    fn stick_direction(context: &InputContext, gamepad: Gamepad) -> Option<Direction> {
+        let axis = |axis_type| {
+            context
+                .gamepad_axes
+                .get(GamepadAxis(gamepad, axis_type))
+                .unwrap_or(0.)
+        };
+        let (x, y) = (
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        if x.abs().max(y.abs()) < STICK_DEAD_ZONE {
+            None
+        } else if x.abs() >= y.abs() {
+            Some(if x > 0. {
+                Direction::RIGHT
+            } else {
+                Direction::LEFT
+            })
+        } else {
+            Some(if y > 0. {
+                Direction::UP
+            } else {
+                Direction::DOWN
+            })
+        }
+    }
+}
+impl InputSource for GamepadSource {
+    fn next_direction(&mut self, context: &InputContext) -> Option<Direction> {
+        let gamepad = context.gamepad?;
+        let pressed = |button_type| context.button_active(GamepadButton(gamepad, button_type));
+        let stick = Self::stick_direction(context, gamepad);
+        let last_stick = std::mem::replace(&mut self.stick, stick);
+        if pressed(GamepadButtonType::DPadLeft) {
+            Some(Direction::LEFT)
+        } else if pressed(GamepadButtonType::DPadRight) {
+            Some(Direction::RIGHT)
+        } else if pressed(GamepadButtonType::DPadUp) {
+            Some(Direction::UP)
+        } else if pressed(GamepadButtonType::DPadDown) {
+            Some(Direction::DOWN)
+        } else {
+            match context.input_mode {
+                InputMode::Held => stick,
+                InputMode::Tapped => stick.filter(|_| stick != last_stick),
+            }
+        }
+    }
+}
+// Several sources steering one snake, say a keyboard and a gamepad. All are asked every
+// frame so each keeps its own state; the first with a direction wins.
+pub struct CombinedSource {
+    pub sources: Vec<Box<dyn InputSource + Send + Sync>>,
+}
+impl InputSource for CombinedSource {
+    fn next_direction(&mut self, context: &InputContext) -> Option<Direction> {
+        self.sources
+            .iter_mut()
+            .map(|source| source.next_direction(context))
+            .fold(None, |first, direction| first.or(direction))
+    }
+}
+// Heads for the food, closing the horizontal gap first, one step at a time. A move that
+// would crash is skipped for the next best one: keeping straight, then any other turn.
+pub struct AiSource;
+impl InputSource for AiSource {
+    fn next_direction(&mut self, context: &InputContext) -> Option<Direction> {
+        // Decide once per step, on the head's latest cell
+        if !context.tick {
+            return None;
+        }
+        let mut candidates = Vec::new();
+        if let Some(food) = context.food {
+            if food.x > context.head.x {
+                candidates.push(Direction::RIGHT);
+            } else if food.x < context.head.x {
+                candidates.push(Direction::LEFT);
+            }
+            if food.y > context.head.y {
+                candidates.push(Direction::UP);
+            } else if food.y < context.head.y {
+                candidates.push(Direction::DOWN);
+            }
+        }
+        candidates.push(context.heading);
+        candidates.extend([
+            Direction::UP,
+            Direction::RIGHT,
+            Direction::DOWN,
+            Direction::LEFT,
+        ]);
+        candidates.into_iter().find(|&direction| {
+            direction != Direction::NONE
+                && direction != context.heading.opposite()
+                && (context.is_safe)(direction)
+        })
+    }
+}
+// Feeds back a recorded run, one direction per tick
+pub struct ReplaySource {
+    pub directions: VecDeque<Direction>,
+}
+impl ReplaySource {
+    pub fn new(directions: Vec<Direction>) -> Self {
+        ReplaySource {
+            directions: directions.into(),
+        }
+    }
+}
+impl InputSource for ReplaySource {
+    fn next_direction(&mut self, context: &InputContext) -> Option<Direction> {
+        if context.tick {
+            self.directions.pop_front()
+        } else {
+            None
+        }
+    }
+}
+// */Input sources
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn always_safe(_: Direction) -> bool {
+        true
+    }
+
+    // The raw inputs an InputContext borrows, nothing pressed until a test presses it
+    #[derive(Default)]
+    struct Inputs {
+        keyboard: Input<KeyCode>,
+        gamepad_buttons: Input<GamepadButton>,
+        gamepad_axes: Axis<GamepadAxis>,
+    }
+
+    impl Inputs {
+        fn context(&self) -> InputContext<'_> {
+            InputContext {
+                keyboard: &self.keyboard,
+                gamepad_buttons: &self.gamepad_buttons,
+                gamepad_axes: &self.gamepad_axes,
+                gamepad: Some(Gamepad(0)),
+                input_mode: InputMode::Held,
+                tick: true,
+                head: GridPos::new(0, 0),
+                food: None,
+                heading: Direction::NONE,
+                is_safe: &always_safe,
+            }
+        }
+    }
+
+    #[test]
+    fn keyboard_source_reads_pressed_key() {
+        let mut inputs = Inputs::default();
+        inputs.keyboard.press(KeyCode::W);
+        let context = inputs.context();
+
+        assert_eq!(
+            KeyboardSource::any(&KeyBindings::default()).next_direction(&context),
+            Some(Direction::UP)
+        );
+    }
+
+    #[test]
+    fn keyboard_source_accepts_arrow_keys() {
+        let mut inputs = Inputs::default();
+        inputs.keyboard.press(KeyCode::Left);
+        let context = inputs.context();
+
+        assert_eq!(
+            KeyboardSource::any(&KeyBindings::default()).next_direction(&context),
+            Some(Direction::LEFT)
+        );
+    }
+
+    #[test]
+    fn split_keyboard_sources_only_read_their_own_keys() {
+        let mut inputs = Inputs::default();
+        inputs.keyboard.press(KeyCode::Up);
+        let context = inputs.context();
+
+        let bindings = KeyBindings::default();
+        assert!(KeyboardSource::player(&bindings, 0)
+            .next_direction(&context)
+            .is_none());
+        assert_eq!(
+            KeyboardSource::player(&bindings, 1).next_direction(&context),
+            Some(Direction::UP)
+        );
+    }
+
+    #[test]
+    fn keyboard_source_follows_rebound_keys() {
+        let mut inputs = Inputs::default();
+        let bindings = KeyBindings {
+            left: [KeyCode::J, KeyCode::Key4],
+            ..Default::default()
+        };
+        inputs.keyboard.press(KeyCode::A);
+        let context = inputs.context();
+        assert!(KeyboardSource::any(&bindings)
+            .next_direction(&context)
+            .is_none());
+
+        inputs.keyboard.press(KeyCode::Key4);
+        let context = inputs.context();
+        assert_eq!(
+            KeyboardSource::any(&bindings).next_direction(&context),
+            Some(Direction::LEFT)
+        );
+    }
+
+    #[test]
+    fn tapped_mode_turns_once_per_press() {
+        let mut inputs = Inputs::default();
+        inputs.keyboard.press(KeyCode::W);
+        let mut context = inputs.context();
+        context.input_mode = InputMode::Tapped;
+        assert_eq!(
+            KeyboardSource::any(&KeyBindings::default()).next_direction(&context),
+            Some(Direction::UP)
+        );
+
+        // The next frame the key is still down but no longer just pressed
+        inputs.keyboard.clear();
+        let mut context = inputs.context();
+        assert_eq!(
+            KeyboardSource::any(&KeyBindings::default()).next_direction(&context),
+            Some(Direction::UP)
+        );
+        context.input_mode = InputMode::Tapped;
+        assert!(KeyboardSource::any(&KeyBindings::default())
+            .next_direction(&context)
+            .is_none());
+    }
+
+    #[test]
+    fn gamepad_source_reads_dpad() {
+        let mut inputs = Inputs::default();
+        inputs
+            .gamepad_buttons
+            .press(GamepadButton(Gamepad(0), GamepadButtonType::DPadLeft));
+        let mut context = inputs.context();
+
+        assert_eq!(
+            GamepadSource::default().next_direction(&context),
+            Some(Direction::LEFT)
+        );
+        context.gamepad = None;
+        assert!(GamepadSource::default().next_direction(&context).is_none());
+    }
+
+    #[test]
+    fn gamepad_source_reads_the_stick_past_the_dead_zone() {
+        let mut inputs = Inputs::default();
+        let stick_x = GamepadAxis(Gamepad(0), GamepadAxisType::LeftStickX);
+        let stick_y = GamepadAxis(Gamepad(0), GamepadAxisType::LeftStickY);
+        let mut source = GamepadSource::default();
+
+        // A little drift doesn't steer
+        inputs.gamepad_axes.set(stick_x, 0.2);
+        inputs.gamepad_axes.set(stick_y, -0.3);
+        let context = inputs.context();
+        assert!(source.next_direction(&context).is_none());
+
+        // Pushed down and a bit right, the stronger axis wins
+        inputs.gamepad_axes.set(stick_y, -0.9);
+        let context = inputs.context();
+        assert_eq!(source.next_direction(&context), Some(Direction::DOWN));
+
+        // Held there, tapped mode only counted the push itself
+        let mut context = inputs.context();
+        context.input_mode = InputMode::Tapped;
+        assert!(source.next_direction(&context).is_none());
+    }
+
+    #[test]
+    fn combined_source_takes_whichever_source_steers() {
+        let mut inputs = Inputs::default();
+        let mut source = CombinedSource {
+            sources: vec![
+                Box::new(KeyboardSource::any(&KeyBindings::default())),
+                Box::new(GamepadSource::default()),
+            ],
+        };
+        inputs
+            .gamepad_buttons
+            .press(GamepadButton(Gamepad(0), GamepadButtonType::DPadUp));
+        let context = inputs.context();
+        assert_eq!(source.next_direction(&context), Some(Direction::UP));
+
+        // Both at once, the keyboard comes first
+        inputs.keyboard.press(KeyCode::Left);
+        let context = inputs.context();
+        assert_eq!(source.next_direction(&context), Some(Direction::LEFT));
+    }
+
+    #[test]
+    fn ai_source_heads_for_food() {
+        let inputs = Inputs::default();
+        let mut context = inputs.context();
+        context.food = Some(GridPos::new(0, -1));
+
+        assert_eq!(AiSource.next_direction(&context), Some(Direction::DOWN));
+        context.tick = false;
+        assert!(AiSource.next_direction(&context).is_none());
+    }
+
+    #[test]
+    fn ai_source_steps_around_a_blocked_cell() {
+        let inputs = Inputs::default();
+        let mut context = inputs.context();
+        context.food = Some(GridPos::new(3, 1));
+        context.heading = Direction::DOWN;
+
+        // Right is the way to the food, up would come next but reverses the snake
+        let right_blocked = |direction: Direction| direction != Direction::RIGHT;
+        context.is_safe = &right_blocked;
+        assert_eq!(AiSource.next_direction(&context), Some(Direction::DOWN));
+
+        let only_left = |direction: Direction| direction == Direction::LEFT;
+        context.is_safe = &only_left;
+        assert_eq!(AiSource.next_direction(&context), Some(Direction::LEFT));
+
+        let boxed_in = |_: Direction| false;
+        context.is_safe = &boxed_in;
+        assert!(AiSource.next_direction(&context).is_none());
+    }
+
+    #[test]
+    fn replay_source_plays_back_one_direction_per_tick() {
+        let inputs = Inputs::default();
+        let mut context = inputs.context();
+        let mut replay = ReplaySource::new(vec![Direction::RIGHT, Direction::UP]);
+
+        assert_eq!(replay.next_direction(&context), Some(Direction::RIGHT));
+        context.tick = false;
+        assert!(replay.next_direction(&context).is_none());
+        context.tick = true;
+        assert_eq!(replay.next_direction(&context), Some(Direction::UP));
+        assert!(replay.next_direction(&context).is_none());
+    }
+}
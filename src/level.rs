@@ -0,0 +1,244 @@
+use crate::resources::{Board, GridConfig, MIN_BOARD_SIDE};
+use std::fmt;
+use std::fs;
+use std::io;
+
+// /*Level tiles
+const WALL_TILE: char = '#';
+const START_TILE: char = 'S';
+const FOOD_TILE: char = 'F';
+const PORTAL_TILE: char = 'O';
+const EMPTY_TILE: char = '.';
+// */Level tiles
+
+// Interior obstacles, the snake start and the starting foods of a board as grid cells.
+// The default is the classic game: no obstacles, the snake in the middle of the window.
+#[derive(Debug, Default, PartialEq)]
+pub struct LevelLayout {
+    pub walls: Vec<(i32, i32)>,
+    pub start: Option<(i32, i32)>,
+    pub foods: Vec<(i32, i32)>,
+    // Either none or the two ends of one portal pair
+    pub portals: Vec<(i32, i32)>,
+    // Board size in cells as (columns, rows), None to fit the board to the window
+    pub size: Option<(i32, i32)>,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    Empty,
+    // Rows are counted from 1, like an editor shows them
+    RaggedRow {
+        row: usize,
+    },
+    UnknownTile {
+        row: usize,
+        column: usize,
+        tile: char,
+    },
+    MultipleStarts,
+    // Portals come in pairs, one on its own or a third has nowhere to lead
+    UnpairedPortals {
+        count: usize,
+    },
+    // Fewer than MIN_BOARD_SIDE cells along a side leaves no room to play
+    TooSmall {
+        columns: usize,
+        rows: usize,
+    },
+}
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(error) => write!(f, "could not read the level: {}", error),
+            LevelError::Empty => write!(f, "the level has no rows"),
+            LevelError::RaggedRow { row } => {
+                write!(f, "row {} is not as wide as the first row", row)
+            }
+            LevelError::UnknownTile { row, column, tile } => {
+                write!(
+                    f,
+                    "unknown tile {:?} at row {}, column {}",
+                    tile, row, column
+                )
+            }
+            LevelError::MultipleStarts => write!(f, "the level has more than one snake start"),
+            LevelError::UnpairedPortals { count } => {
+                write!(f, "the level has {} portals instead of a pair", count)
+            }
+            LevelError::TooSmall { columns, rows } => write!(
+                f,
+                "the level is {}x{} cells, it needs at least {} a side",
+                columns, rows, MIN_BOARD_SIDE
+            ),
+        }
+    }
+}
+impl From<io::Error> for LevelError {
+    fn from(error: io::Error) -> Self {
+        LevelError::Io(error)
+    }
+}
+
+impl LevelLayout {
+    // A level to play on, unlike `parse` this also turns down boards too small for a round
+    pub fn load(path: &str) -> Result<Self, LevelError> {
+        let layout = Self::parse(&fs::read_to_string(path)?)?;
+        match layout.size {
+            Some((columns, rows)) if columns.min(rows) < MIN_BOARD_SIDE as i32 => {
+                Err(LevelError::TooSmall {
+                    columns: columns as usize,
+                    rows: rows as usize,
+                })
+            }
+            _ => Ok(layout),
+        }
+    }
+    // The first line of the map is the top row of the board, grid rows count from the bottom
+    pub fn parse(map: &str) -> Result<Self, LevelError> {
+        let lines: Vec<&str> = map.lines().map(str::trim_end).collect();
+        let rows = lines.len();
+        let columns = lines.first().map_or(0, |line| line.chars().count());
+        if rows == 0 || columns == 0 {
+            return Err(LevelError::Empty);
+        }
+
+        let mut layout = LevelLayout {
+            size: Some((columns as i32, rows as i32)),
+            ..Default::default()
+        };
+        for (line_index, line) in lines.iter().enumerate() {
+            if line.chars().count() != columns {
+                return Err(LevelError::RaggedRow {
+                    row: line_index + 1,
+                });
+            }
+            let y = (rows - 1 - line_index) as i32;
+            for (x, tile) in line.chars().enumerate() {
+                let cell = (x as i32, y);
+                match tile {
+                    WALL_TILE => layout.walls.push(cell),
+                    FOOD_TILE => layout.foods.push(cell),
+                    PORTAL_TILE => layout.portals.push(cell),
+                    START_TILE if layout.start.is_some() => return Err(LevelError::MultipleStarts),
+                    START_TILE => layout.start = Some(cell),
+                    EMPTY_TILE => {}
+                    _ => {
+                        return Err(LevelError::UnknownTile {
+                            row: line_index + 1,
+                            column: x + 1,
+                            tile,
+                        })
+                    }
+                }
+            }
+        }
+        if !matches!(layout.portals.len(), 0 | 2) {
+            return Err(LevelError::UnpairedPortals {
+                count: layout.portals.len(),
+            });
+        }
+        Ok(layout)
+    }
+    pub fn board(&self) -> Option<Board> {
+        self.size.map(|(cols, rows)| Board {
+            cols: cols as u32,
+            rows: rows as u32,
+        })
+    }
+    pub fn start_cell(&self, grid_config: &GridConfig, board: &Board) -> (i32, i32) {
+        self.start.unwrap_or_else(|| grid_config.spawn_cell(board))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tiles_with_rows_counted_from_the_bottom() {
+        let layout = LevelLayout::parse("#..\n.S.\n..F\n").unwrap();
+        assert_eq!(layout.size, Some((3, 3)));
+        assert_eq!(layout.walls, vec![(0, 2)]);
+        assert_eq!(layout.start, Some((1, 1)));
+        assert_eq!(layout.foods, vec![(2, 0)]);
+        assert_eq!(layout.board(), Some(Board { cols: 3, rows: 3 }));
+    }
+
+    #[test]
+    fn portals_come_in_pairs() {
+        let layout = LevelLayout::parse("O..\n.S.\n..O\n").unwrap();
+        assert_eq!(layout.portals, vec![(0, 2), (2, 0)]);
+
+        assert!(matches!(
+            LevelLayout::parse("O..\n.S.\n...\n"),
+            Err(LevelError::UnpairedPortals { count: 1 })
+        ));
+        assert!(matches!(
+            LevelLayout::parse("O.O\n.S.\n..O\n"),
+            Err(LevelError::UnpairedPortals { count: 3 })
+        ));
+    }
+
+    #[test]
+    fn malformed_levels_are_rejected() {
+        assert!(matches!(LevelLayout::parse(""), Err(LevelError::Empty)));
+        assert!(matches!(
+            LevelLayout::parse("...\n..\n"),
+            Err(LevelError::RaggedRow { row: 2 })
+        ));
+        assert!(matches!(
+            LevelLayout::parse("..\n.x\n"),
+            Err(LevelError::UnknownTile {
+                row: 2,
+                column: 2,
+                tile: 'x'
+            })
+        ));
+        assert!(matches!(
+            LevelLayout::parse("S.\n.S\n"),
+            Err(LevelError::MultipleStarts)
+        ));
+        assert!(matches!(
+            LevelLayout::load("no/such/level.txt"),
+            Err(LevelError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn levels_too_small_to_play_are_not_loaded() {
+        let path = std::env::temp_dir().join("rusnake_tiny_level.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "S.\n..\n").unwrap();
+        let error = LevelLayout::load(path).unwrap_err();
+        assert!(matches!(
+            error,
+            LevelError::TooSmall {
+                columns: 2,
+                rows: 2
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the level is 2x2 cells, it needs at least {} a side",
+                MIN_BOARD_SIDE
+            )
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sample_levels_parse() {
+        for map in [
+            include_str!("../assets/levels/classic.txt"),
+            include_str!("../assets/levels/box.txt"),
+        ] {
+            let layout = LevelLayout::parse(map).unwrap();
+            assert_eq!(layout.size, Some((16, 12)));
+            assert_eq!(layout.start, Some((8, 6)));
+            assert_eq!(layout.foods, vec![(9, 7)]);
+        }
+    }
+}
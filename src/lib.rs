@@ -0,0 +1,26 @@
+pub mod components;
+pub mod events;
+pub mod input;
+pub mod level;
+pub mod plugin;
+pub mod resources;
+
+use bevy::prelude::*;
+pub use plugin::{SimulationPlugin, SnakePlugin};
+
+// The whole game in its own window, ready to run. Callers embedding it can add their
+// own plugins and systems first; SimulationPlugin alone runs the rules without a window.
+pub fn build_app() -> App {
+    let mut app = App::new();
+    app.insert_resource(WindowDescriptor {
+        title: "rusnake".to_string(),
+        width: 800.,
+        height: 600.,
+        // setup_system applies the resizable setting once it's loaded
+        resizable: false,
+        ..Default::default()
+    })
+    .add_plugins(DefaultPlugins)
+    .add_plugin(SnakePlugin);
+    app
+}
@@ -0,0 +1,5184 @@
+use crate::components::*;
+// Named explicitly so it wins over the Direction in bevy's prelude
+use crate::components::Direction;
+use crate::events::*;
+use crate::input::*;
+use crate::level::LevelLayout;
+use crate::resources::*;
+use bevy::app::AppExit;
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::ecs::schedule::{RunCriteriaDescriptorCoercion, ShouldRun};
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy::utils::HashSet;
+use bevy::window::{WindowId, WindowMode, WindowResized};
+use rand::Rng;
+
+// /* Enums
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum Labels {
+    INPUT,
+    HeadMove,
+    TailMove,
+    SPAWN,
+    COLLISION,
+    SCORE,
+    START,
+    SYNC,
+}
+// Shared by every system set that runs once per step
+#[derive(Debug, Clone, PartialEq, Eq, Hash, RunCriteriaLabel)]
+pub struct StepCriteria;
+// */ Enums
+
+// /*Game Constants
+const HIGH_SCORE_PATH: &str = "highscore.txt";
+const SETTINGS_PATH: &str = "settings.toml";
+const REPLAY_PATH: &str = "replay.txt";
+const LEVEL_PATH: &str = "assets/levels/classic.txt";
+// A pre-grown snake is laid out as if it had been moving this way
+const START_DIRECTION: Direction = Direction::RIGHT;
+// Game clock seconds a finished round stays up before a restart key is taken
+const RESTART_DELAY_SECONDS: f64 = 0.5;
+// */Game Constants
+
+// /*Asset constants
+pub const GRID_LAYER: f32 = -1.;
+pub const FOOD_LAYER: f32 = 0.;
+pub const SNAKE_LAYER: f32 = 1.;
+const FONT_PATH: &str = "fonts/FiraMono-Medium.ttf";
+const FOOD_FADE_SECONDS: f32 = 0.3;
+const EAT_FLASH_SECONDS: f32 = 0.25;
+const GROW_IN_SECONDS: f32 = 0.15;
+const DEATH_SECONDS: f32 = 0.5;
+// How long the camera shakes after a crash and how far off center it goes, in cells
+const SHAKE_SECONDS: f32 = 0.3;
+const SHAKE_CELLS: f32 = 0.3;
+// Steps before the edge a wrap warning starts to show, its width in cells and its alpha at full
+const EDGE_GLOW_CELLS: i32 = 3;
+const EDGE_GLOW_WIDTH: f32 = 0.25;
+const EDGE_GLOW_ALPHA: f32 = 0.6;
+const HUD_FONT_SIZE: f32 = 30.;
+const TOAST_FADE_SECONDS: f32 = 0.3;
+const HIGH_SCORE_TOAST_SECONDS: f32 = 2.;
+const SPLASH_SECONDS: f32 = 2.;
+// */Asset constants
+
+// The whole game: the rules from SimulationPlugin plus the window side of it, the
+// menu, the HUD and sound. Expects DefaultPlugins to be added first.
+pub struct SnakePlugin;
+impl Plugin for SnakePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(SimulationPlugin)
+            // The window starts on the splash, the headless rules straight on the menu
+            .insert_resource(State::new(AppState::Splash))
+            .add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_startup_system(setup_system)
+            .add_startup_system(setup_hud)
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup_grid)
+            .add_system_set(SystemSet::on_enter(AppState::Splash).with_system(setup_splash))
+            .add_system_set(SystemSet::on_update(AppState::Splash).with_system(end_splash))
+            .add_system_set(SystemSet::on_exit(AppState::Splash).with_system(cleanup_splash))
+            .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_menu))
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(start_game)
+                    .with_system(watch_replay)
+                    .with_system(open_options)
+                    .with_system(choose_player_count)
+                    .with_system(choose_difficulty)
+                    .with_system(choose_mode),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Menu)
+                    .with_system(cleanup_menu)
+                    .with_system(apply_settings),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Options).with_system(setup_options))
+            .add_system_set(SystemSet::on_update(AppState::Options).with_system(edit_options))
+            .add_system_set(SystemSet::on_exit(AppState::Options).with_system(cleanup_menu))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(pause_menu)
+                    .with_system(toast_countdown),
+            )
+            .add_system(resize_board)
+            .add_system(update_score_text.after(Labels::SCORE))
+            .add_system(update_time_text)
+            .add_system(update_combo_text.after(Labels::SCORE))
+            .add_system(update_objective_text.after(Labels::COLLISION))
+            .add_system(update_dash_text)
+            .add_system(update_practice_text)
+            .add_system(update_pause_text)
+            .add_event::<Toast>()
+            .add_system(show_toasts)
+            .add_system(fade_toasts)
+            .add_system(toggle_mute)
+            .add_system(save_settings)
+            .add_system(toggle_grid)
+            .add_system(toggle_diagnostics)
+            .add_system(toggle_fullscreen)
+            .add_system(update_diagnostics_text)
+            .add_system(toggle_cell_overlay)
+            .add_system(update_cell_overlay_text.after(Labels::SPAWN))
+            .add_system(cycle_theme)
+            .add_system(recolor_board)
+            .add_system(fit_view_to_window)
+            .add_system(fit_camera.after(resize_board).after(fit_view_to_window))
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(StepCriteria)
+                    .with_system(track_motion.after(Labels::HeadMove)),
+            )
+            .add_system(update_head_facing.after(Labels::HeadMove))
+            .add_system(color_snake)
+            .add_system(flash_head.after(color_snake))
+            .add_system(start_dying.after(Labels::COLLISION))
+            .add_system(animate_death.after(flash_head).before(shape_snake))
+            .add_system(start_shake.after(Labels::COLLISION))
+            .add_system(shake_camera.after(start_shake))
+            .add_system(glow_wrap_edges.after(Labels::HeadMove))
+            .add_system(shape_snake.after(Labels::SPAWN))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                render_interpolate
+                    .after(Labels::SYNC)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_system(update_victory_text.after(Labels::COLLISION))
+            .add_system(play_eat_sound.after(Labels::COLLISION))
+            .add_system(spawn_food_ghost.after(Labels::COLLISION))
+            .add_system(start_eat_flash.after(Labels::COLLISION))
+            .add_system(fade_out)
+            .add_system(grow_in)
+            .add_system(finish_round.after(Labels::SCORE))
+            .add_system(save_recording.after(Labels::COLLISION));
+        #[cfg(feature = "debug-controls")]
+        app.add_system(debug_step_controls)
+            .add_system_set(SystemSet::on_update(AppState::Menu).with_system(toggle_stress_test));
+    }
+}
+
+// The game rules without any window, rendering, audio or asset loading. Needs a Board
+// resource from the caller; runs under DefaultPlugins as well as MinimalPlugins.
+pub struct SimulationPlugin;
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Time>()
+            .init_resource::<Input<KeyCode>>()
+            .init_resource::<Input<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .init_resource::<Gamepads>()
+            .add_state(AppState::Menu)
+            .insert_resource(GameClock::default())
+            .insert_resource(StepAccumulator::new(0.))
+            .insert_resource(Tick::new())
+            .init_resource::<DebugStep>()
+            .insert_resource(Score { value: 0 })
+            .insert_resource(Combo::new())
+            .insert_resource(GameState::Running)
+            .insert_resource(BoundaryMode::Wall)
+            .insert_resource(SelfCollisionMode::Die)
+            .insert_resource(ScoringMode::Points)
+            .insert_resource(InputMode::Held)
+            .init_resource::<KeyBindings>()
+            .insert_resource(Paused(false))
+            .insert_resource(StepInterval::new())
+            .insert_resource(StartInterval(TIME_STEP as f64))
+            .insert_resource(Difficulty::Normal)
+            .insert_resource(UTurnGuard(false))
+            .insert_resource(EarlyTurns(false))
+            .insert_resource(RngSeed(None))
+            .insert_resource(GameRng::new(rand::random()))
+            .insert_resource(Recording::new(0))
+            .insert_resource(Playback {
+                recording: None,
+                step: 0,
+            })
+            .insert_resource(GridConfig::new())
+            .insert_resource(FoodDecay(true))
+            .insert_resource(StartLength(START_LENGTH))
+            .insert_resource(PlayerCount(1))
+            .insert_resource(AiEnabled(false))
+            .insert_resource(StartDirection(Direction::NONE))
+            .insert_resource(CountdownLength(COUNTDOWN_SECONDS))
+            .insert_resource(Countdown::new(0., 0.))
+            .insert_resource(TimeLimit(None))
+            .insert_resource(Objective::new(0))
+            .insert_resource(CollisionsEnabled(true))
+            .insert_resource(MaxLength(None))
+            .init_resource::<StressTest>()
+            .init_resource::<Dash>()
+            .insert_resource(TimeRemaining::new(None, 0.))
+            .insert_resource(FoodCount(FOOD_COUNT))
+            .insert_resource(GoldenFoodChance(GOLDEN_FOOD_CHANCE))
+            .insert_resource(RottenFoodChance(ROTTEN_FOOD_CHANCE))
+            .insert_resource(LevelLayout::default())
+            .insert_resource(OccupiedCells::default())
+            .insert_resource(Theme::default())
+            .add_event::<FoodEaten>()
+            .add_event::<GameOver>()
+            .add_event::<GameReset>()
+            .add_system_to_stage(CoreStage::PreUpdate, advance_game_clock)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                sync_transforms
+                    .label(Labels::SYNC)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::InGame)
+                    .with_system(start_round.label(Labels::START))
+                    .with_system(initialize_snake)
+                    .with_system(initialize_food.after(Labels::START))
+                    .with_system(initialize_walls)
+                    .with_system(initialize_portals)
+                    .with_system(initialize_edge_glows),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(reset_game)
+                    .with_system(toggle_pause)
+                    .with_system(get_next_move.label(Labels::INPUT))
+                    .with_system(request_dash.label(Labels::INPUT))
+                    .with_system(finish_countdown.after(Labels::INPUT))
+                    .with_system(count_down_time)
+                    .with_system(score_by_length),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(run_due_steps.label(StepCriteria))
+                    .with_system(
+                        play_back_moves
+                            .after(Labels::INPUT)
+                            .before(Labels::HeadMove),
+                    )
+                    .with_system(move_snake.label(Labels::HeadMove).after(Labels::INPUT))
+                    .with_system(record_moves.after(Labels::HeadMove))
+                    .with_system(eat_food.label(Labels::COLLISION).after(Labels::HeadMove))
+                    .with_system(
+                        award_food_score
+                            .label(Labels::SCORE)
+                            .after(Labels::COLLISION),
+                    )
+                    .with_system(count_objective_foods.after(Labels::COLLISION))
+                    .with_system(expire_timed_food.after(Labels::COLLISION))
+                    .with_system(decay_food_value.before(Labels::COLLISION))
+                    .with_system(decay_combo.before(Labels::COLLISION))
+                    .with_system(
+                        collision_check
+                            .label(Labels::COLLISION)
+                            .after(Labels::HeadMove)
+                            .after(Labels::TailMove),
+                    )
+                    .with_system(
+                        spawn_new_tail
+                            .label(Labels::SPAWN)
+                            .before(Labels::HeadMove)
+                            .before(Labels::TailMove),
+                    ),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(cleanup_game));
+    }
+}
+
+fn advance_game_clock(time: Res<Time>, mut clock: ResMut<GameClock>) {
+    clock.seconds += time.delta_seconds_f64();
+}
+
+// Runs the step systems once for every step due. After a stall that means several steps
+// back to back within one frame, each seeing the moves and growth of the one before.
+#[allow(clippy::too_many_arguments)]
+fn run_due_steps(
+    app_state: Res<State<AppState>>,
+    clock: Res<GameClock>,
+    game_state: Res<GameState>,
+    paused: Res<Paused>,
+    countdown: Res<Countdown>,
+    step_interval: Res<StepInterval>,
+    early_turns: Res<EarlyTurns>,
+    mut debug_step: ResMut<DebugStep>,
+    turn_query: Query<&NextDirection, With<Head>>,
+    mut accumulator: ResMut<StepAccumulator>,
+    mut tick: ResMut<Tick>,
+) -> ShouldRun {
+    let playing = *app_state.current() == AppState::InGame
+        && *game_state == GameState::Running
+        && !countdown.running(clock.seconds);
+    let interval = debug_step.scaled(step_interval.seconds);
+    if playing && !paused.0 {
+        // The first step waits a full interval from the end of the countdown
+        let filled_to = accumulator.filled_to.max(countdown.ends_at);
+        accumulator.skip(filled_to);
+        accumulator.fill(clock.seconds, interval);
+        // Going straight keeps to the regular steps, only a turn may come early
+        if early_turns.0 && turn_query.iter().any(|turns| !turns.queue.is_empty()) {
+            accumulator.hurry(interval);
+        }
+        tick.due = accumulator.steps_due(interval);
+    } else if playing && debug_step.step_once {
+        // A single debug step of the paused game, the accumulator stays empty
+        debug_step.step_once = false;
+        accumulator.skip(clock.seconds);
+        tick.due = 1;
+        tick.allowed = true;
+        return ShouldRun::YesAndCheckAgain;
+    } else {
+        accumulator.skip(clock.seconds);
+        tick.due = 0;
+    }
+
+    tick.allowed = tick.due > 0;
+    if tick.allowed {
+        accumulator.take_step(interval);
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn setup_system(
+    mut commands: Commands,
+    mut windows: ResMut<Windows>,
+    asset_server: Res<AssetServer>,
+    grid_config: Res<GridConfig>,
+) {
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(GameCamera);
+    commands.spawn_bundle(UiCameraBundle::default());
+
+    // A level fixes the board size, otherwise the settings do, starting out with the biggest
+    // board that fits the window. The window is resized for any other board.
+    let window = windows.get_primary_mut().unwrap();
+    let level_layout = LevelLayout::load(LEVEL_PATH).unwrap_or_else(|error| {
+        warn!("could not load {}: {}", LEVEL_PATH, error);
+        LevelLayout::default()
+    });
+    let window_size = WinSize {
+        w: window.width(),
+        h: window.height(),
+    };
+    let window_board = Board::fit(&window_size, &grid_config, BOARD_MARGIN);
+    let settings = Settings::load(SETTINGS_PATH, Settings::new(window_board));
+    window.set_resizable(settings.resizable);
+    commands.insert_resource(settings.view_mode);
+    let board = level_layout.board().unwrap_or(settings.board);
+    let needed = board.win_size(&grid_config, BOARD_MARGIN);
+    // A window too small even for the smallest board grows to fit it
+    let win_size =
+        if board == window_board && needed.w <= window_size.w && needed.h <= window_size.h {
+            window_size
+        } else {
+            window.set_resolution(needed.w, needed.h);
+            needed
+        };
+    commands.insert_resource(win_size);
+    commands.insert_resource(board);
+    commands.insert_resource(level_layout);
+
+    let theme = Theme::named(settings.theme).unwrap_or_default();
+    commands.insert_resource(ClearColor(theme.background));
+    commands.insert_resource(theme);
+    commands.insert_resource(settings.boundary_mode);
+    commands.insert_resource(FoodCount(settings.food_count));
+    commands.insert_resource(StartInterval(settings.step_interval()));
+    commands.insert_resource(Muted(settings.muted));
+    commands.insert_resource(RngSeed(settings.seed));
+    commands.insert_resource(GameRng::new(settings.seed.unwrap_or_else(rand::random)));
+    commands.insert_resource(settings.keys);
+    commands.insert_resource(settings);
+    commands.insert_resource(HighScore::load(HIGH_SCORE_PATH));
+
+    commands.insert_resource(Sounds {
+        eat: asset_server.load("eat.ogg"),
+        crash: asset_server.load("heyronii.ogg"),
+    });
+    commands.insert_resource(ShowGrid(true));
+    commands.insert_resource(ShowDiagnostics(false));
+    commands.insert_resource(ShowCellOverlay(false));
+    commands.insert_resource(SmoothMotion(false));
+}
+
+// A checkerboard under the playfield, placed through the same lattice as everything on it
+fn setup_grid(
+    mut commands: Commands,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    show_grid: Res<ShowGrid>,
+    theme: Res<Theme>,
+) {
+    spawn_grid_tiles(&mut commands, &board, &grid_config, &show_grid, &theme);
+    spawn_border(&mut commands, &board, &grid_config, &theme);
+}
+
+fn spawn_grid_tiles(
+    commands: &mut Commands,
+    board: &Board,
+    grid_config: &GridConfig,
+    show_grid: &ShowGrid,
+    theme: &Theme,
+) {
+    for x in 0..board.cols as i32 {
+        for y in 0..board.rows as i32 {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: theme.grid[((x + y) % 2) as usize],
+                        custom_size: Some(grid_config.cell_size()),
+                        ..Default::default()
+                    },
+                    transform: Transform {
+                        translation: grid_config.grid_to_world(x, y, board).extend(GRID_LAYER),
+                        ..Default::default()
+                    },
+                    visibility: Visibility {
+                        is_visible: show_grid.0,
+                    },
+                    ..Default::default()
+                })
+                .insert(GridTile);
+        }
+    }
+}
+
+// Wall colored strips filling the margin around the board, their inner edges on the
+// board's edges
+fn spawn_border(commands: &mut Commands, board: &Board, grid_config: &GridConfig, theme: &Theme) {
+    for (center, size) in border_strips(board, grid_config, BOARD_MARGIN) {
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: theme.wall,
+                    custom_size: Some(size),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(center.extend(GRID_LAYER)),
+                ..Default::default()
+            })
+            .insert(Border);
+    }
+}
+
+// Center and size of the top, bottom, left and right strips, `width` thick. The top and
+// bottom ones run over the corners.
+fn border_strips(board: &Board, grid_config: &GridConfig, width: f32) -> [(Vec2, Vec2); 4] {
+    let inner = board.win_size(grid_config, 0.);
+    let outer = board.win_size(grid_config, width);
+    let across = Vec2::new(outer.w, width);
+    let down = Vec2::new(width, inner.h);
+    let (x, y) = ((inner.w + width) / 2., (inner.h + width) / 2.);
+    [
+        (Vec2::new(0., y), across),
+        (Vec2::new(0., -y), across),
+        (Vec2::new(-x, 0.), down),
+        (Vec2::new(x, 0.), down),
+    ]
+}
+
+fn setup_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let text_style = TextStyle {
+        font: asset_server.load(FONT_PATH),
+        font_size: HUD_FONT_SIZE,
+        color: Color::WHITE,
+    };
+    let section = |value: &str| TextSection {
+        value: value.to_string(),
+        style: text_style.clone(),
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.),
+                    left: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    section("Score: "),
+                    section("0"),
+                    section("  Length: "),
+                    section("1"),
+                    section("  Best: "),
+                    section("0"),
+                    section(""),
+                    section(""),
+                    section(""),
+                    section(""),
+                    section(""),
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(ScoreText);
+
+    let pause_sections = ["PAUSED\n\n".to_string()]
+        .into_iter()
+        .chain(pause_lines(0))
+        .map(|value| section(&value))
+        .collect();
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(35.),
+                    left: Val::Percent(38.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: pause_sections,
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(PauseText);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(45.),
+                    left: Val::Percent(25.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![section("YOU WIN! Press Space to restart")],
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(VictoryText);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.),
+                    right: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![section("")],
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(DiagnosticsText);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.),
+                    left: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![section("")],
+                ..Default::default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(CellOverlayText);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn initialize_snake(
+    mut commands: Commands,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    start_length: Res<StartLength>,
+    level_layout: Res<LevelLayout>,
+    theme: Res<Theme>,
+    player_count: Res<PlayerCount>,
+    ai_enabled: Res<AiEnabled>,
+    start: Res<StartDirection>,
+    stress_test: Res<StressTest>,
+    key_bindings: Res<KeyBindings>,
+    mut occupied: ResMut<OccupiedCells>,
+) {
+    for player in (0..snake_count(&player_count, &ai_enabled)).map(Player) {
+        let heading = spawn_direction(player, &start);
+        let start_cells = player_start_cells(
+            player,
+            &level_layout,
+            &grid_config,
+            &board,
+            &start,
+            start_length.0,
+            &stress_test,
+        );
+        for &cell in &start_cells {
+            occupied.add(cell);
+        }
+        // With a second player each takes their own half of the keyboard. Alone, the
+        // first connected gamepad steers too.
+        let source: Box<dyn InputSource + Send + Sync> =
+            match (player_count.0, ai_enabled.0, player.0) {
+                (_, true, 1) => Box::new(AiSource),
+                (1, _, _) => Box::new(CombinedSource {
+                    sources: vec![
+                        Box::new(KeyboardSource::any(&key_bindings)),
+                        Box::new(GamepadSource::default()),
+                    ],
+                }),
+                (_, _, 0) => Box::new(KeyboardSource::player(&key_bindings, 0)),
+                _ => Box::new(KeyboardSource::player(&key_bindings, 1)),
+            };
+        let head_entity = spawn_head(
+            &mut commands,
+            &grid_config,
+            &board,
+            &theme,
+            player,
+            start_cells[0].into(),
+            heading,
+            source,
+        );
+        let mut segments = vec![head_entity];
+
+        // The rest of the body trails off behind the head, one cell per segment
+        for (index, &cell) in start_cells.iter().enumerate().skip(1) {
+            let color = theme.player_segment_color(player, index, start_cells.len());
+            let tail_entity = spawn_tail(
+                &mut commands,
+                &grid_config,
+                &board,
+                player,
+                cell.into(),
+                color,
+                false,
+            );
+            segments.push(tail_entity);
+        }
+        commands.entity(head_entity).insert(Snake { segments });
+    }
+}
+
+fn snake_count(player_count: &PlayerCount, ai_enabled: &AiEnabled) -> u8 {
+    if ai_enabled.0 {
+        player_count.0.max(2)
+    } else {
+        player_count.0
+    }
+}
+
+// The second player starts mirrored through the middle of the board, heading the other way
+fn player_start_cell(
+    player: Player,
+    level_layout: &LevelLayout,
+    grid_config: &GridConfig,
+    board: &Board,
+) -> (i32, i32) {
+    let (x, y) = level_layout.start_cell(grid_config, board);
+    if player.0 == 0 {
+        (x, y)
+    } else {
+        (board.cols as i32 - 1 - x, board.rows as i32 - 1 - y)
+    }
+}
+
+fn start_direction(player: Player) -> Direction {
+    if player.0 == 0 {
+        START_DIRECTION
+    } else {
+        START_DIRECTION.opposite()
+    }
+}
+
+fn spawn_direction(player: Player, start: &StartDirection) -> Direction {
+    if player.0 == 0 {
+        start.0
+    } else {
+        start.0.opposite()
+    }
+}
+
+// Where a snake spawning with `heading` faces and lays its body out from
+fn facing_direction(player: Player, heading: Direction) -> Direction {
+    if heading == Direction::NONE {
+        start_direction(player)
+    } else {
+        heading
+    }
+}
+
+// Cells `player`'s snake starts a round on, head first
+fn player_start_cells(
+    player: Player,
+    level_layout: &LevelLayout,
+    grid_config: &GridConfig,
+    board: &Board,
+    start: &StartDirection,
+    start_length: usize,
+    stress_test: &StressTest,
+) -> Vec<(i32, i32)> {
+    if stress_test.0 && player.0 == 0 {
+        return serpentine_cells(board, STRESS_LENGTH);
+    }
+    snake_start_cells(
+        player_start_cell(player, level_layout, grid_config, board),
+        facing_direction(player, spawn_direction(player, start)),
+        start_length,
+    )
+}
+
+// `length` cells winding down the board row by row from its top right, head first and
+// heading right like a fresh snake. The last column and the two bottom rows are left free
+// to move on, a longer snake is cut down to what fits around them. Meant for the open
+// board, walls of a level aren't kept clear.
+fn serpentine_cells(board: &Board, length: usize) -> Vec<(i32, i32)> {
+    let width = board.cols.saturating_sub(1).max(1) as usize;
+    let rows = board.rows.saturating_sub(2).max(1) as usize;
+    (0..length.clamp(1, width * rows))
+        .map(|index| {
+            let (row, along) = (index / width, index % width);
+            let x = if row % 2 == 0 {
+                width - 1 - along
+            } else {
+                along
+            };
+            (x as i32, board.rows as i32 - 1 - row as i32)
+        })
+        .collect()
+}
+
+// Cells of a freshly spawned snake, head first, laid out as if it had been moving `direction`
+fn snake_start_cells(
+    (spawn_x, spawn_y): (i32, i32),
+    direction: Direction,
+    start_length: usize,
+) -> Vec<(i32, i32)> {
+    let behind = direction.opposite().velocity();
+    (0..start_length.max(1))
+        .map(|segment| {
+            let offset = behind * segment as f32;
+            (spawn_x + offset.x as i32, spawn_y + offset.y as i32)
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_head(
+    commands: &mut Commands,
+    grid_config: &GridConfig,
+    board: &Board,
+    theme: &Theme,
+    player: Player,
+    pos: GridPos,
+    heading: Direction,
+    source: Box<dyn InputSource + Send + Sync>,
+) -> Entity {
+    let head_size = grid_config.head_size();
+    let spawn_position = grid_config.grid_to_world(pos.x, pos.y, board);
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: theme.player_segment_color(player, 0, 1),
+                custom_size: Some(Vec2::new(head_size, head_size)),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation: spawn_position.extend(SNAKE_LAYER),
+                rotation: facing_rotation(facing_direction(player, heading)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Head)
+        .insert(player)
+        .insert(pos)
+        .insert(Motion::new(spawn_position.extend(SNAKE_LAYER)))
+        .insert(Velocity { direction: heading })
+        // Empty, the snake keeps going `heading` until the first turn
+        .insert(NextDirection::new())
+        .insert(LateSpawn::new())
+        .insert(Controller { source })
+        .with_children(|head| {
+            // Two eyes towards the front edge, the head is rotated to face where it goes
+            let eye_size = grid_config.shorter_side() / 6.;
+            for side in [-1., 1.] {
+                head.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: theme.eyes,
+                        custom_size: Some(Vec2::new(eye_size, eye_size)),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(head_size / 4., side * head_size / 4., 0.1),
+                    ..Default::default()
+                })
+                .insert(Eye);
+            }
+        })
+        .id()
+}
+
+// Turns the head, eyes facing right unrotated, towards its direction. A snake that
+// hasn't moved yet faces the way its body was laid out.
+fn facing_rotation(direction: Direction) -> Quat {
+    let angle = match direction {
+        Direction::NONE => return facing_rotation(START_DIRECTION),
+        Direction::RIGHT => 0.,
+        Direction::UP => std::f32::consts::FRAC_PI_2,
+        Direction::LEFT => std::f32::consts::PI,
+        Direction::DOWN => -std::f32::consts::FRAC_PI_2,
+    };
+    Quat::from_rotation_z(angle)
+}
+
+// Shades the snake along its length whenever it grows or the theme changes
+#[allow(clippy::type_complexity)]
+fn color_snake(
+    theme: Res<Theme>,
+    snake_query: Query<(&Player, &Snake)>,
+    changed_snakes: Query<(), Changed<Snake>>,
+    mut sprite_query: Query<&mut Sprite, Or<(With<Head>, With<Tail>)>>,
+) {
+    if !theme.is_changed() && changed_snakes.is_empty() {
+        return;
+    }
+    for (player, segments) in snakes_by_player(&snake_query) {
+        for (index, entity) in segments.iter().enumerate() {
+            if let Ok(mut sprite) = sprite_query.get_mut(*entity) {
+                sprite.color = theme.player_segment_color(player, index, segments.len());
+            }
+        }
+    }
+}
+
+// Turns every body segment towards the one in front so its joint bridges the gap, and
+// narrows the last segment into the tip. A neighbor that isn't on a next-door cell,
+// across a wrapped edge or a portal or not yet moved off a fresh segment, gets no joint.
+#[allow(clippy::type_complexity)]
+fn shape_snake(
+    grid_config: Res<GridConfig>,
+    snake_query: Query<&Snake>,
+    pos_query: Query<&GridPos>,
+    mut tail_query: Query<(&mut Transform, &mut Sprite, &Children), (With<Tail>, Without<Joint>)>,
+    mut joint_query: Query<
+        (&mut Sprite, &mut Transform, &mut Visibility),
+        (With<Joint>, Without<Tail>),
+    >,
+) {
+    for snake in snake_query.iter() {
+        for (index, pair) in snake.segments.windows(2).enumerate() {
+            let (front, segment) = (pair[0], pair[1]);
+            let towards_front = match (pos_query.get(segment), pos_query.get(front)) {
+                (Ok(pos), Ok(front_pos)) => neighbor_direction(*pos, *front_pos),
+                _ => None,
+            };
+            let (mut transform, mut sprite, children) = match tail_query.get_mut(segment) {
+                Ok(tail) => tail,
+                Err(_) => continue,
+            };
+            if let Some(direction) = towards_front {
+                transform.rotation = facing_rotation(direction);
+            }
+            let size = if index + 2 == snake.segments.len() {
+                grid_config.tail_tip_size()
+            } else {
+                grid_config.tail_size()
+            };
+            sprite.custom_size = Some(Vec2::new(size, size));
+            for child in children.iter() {
+                if let Ok((mut joint_sprite, mut joint_transform, mut visibility)) =
+                    joint_query.get_mut(*child)
+                {
+                    joint_sprite.color = sprite.color;
+                    visibility.is_visible = towards_front.is_some();
+                    // Cells that aren't square are a different length across and down
+                    if let Some(direction) = towards_front {
+                        let length = grid_config.step_length(direction);
+                        joint_sprite.custom_size = Some(Vec2::new(length, grid_config.tail_size()));
+                        joint_transform.translation.x = length / 2.;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The way from one cell to the next-door cell `to`, None if they don't touch
+fn neighbor_direction(from: GridPos, to: GridPos) -> Option<Direction> {
+    match (to.x - from.x, to.y - from.y) {
+        (1, 0) => Some(Direction::RIGHT),
+        (-1, 0) => Some(Direction::LEFT),
+        (0, 1) => Some(Direction::UP),
+        (0, -1) => Some(Direction::DOWN),
+        _ => None,
+    }
+}
+
+// Every snake's segments, head first, in player order
+fn snakes_by_player<'a>(snake_query: &'a Query<(&Player, &Snake)>) -> Vec<(Player, &'a [Entity])> {
+    let mut snakes: Vec<(Player, &[Entity])> = snake_query
+        .iter()
+        .map(|(player, snake)| (*player, snake.segments.as_slice()))
+        .collect();
+    snakes.sort_by_key(|(player, _)| *player);
+    snakes
+}
+
+fn cycle_theme(kb: Res<Input<KeyCode>>, mut theme: ResMut<Theme>) {
+    if kb.just_pressed(KeyCode::T) {
+        *theme = theme.next();
+    }
+}
+
+// Repaints everything but the snake body, which color_snake handles, on a theme change
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn recolor_board(
+    theme: Res<Theme>,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    mut clear_color: ResMut<ClearColor>,
+    mut food_query: Query<(&mut Sprite, &ValueTimer, Option<&Golden>, Option<&Rotten>), With<Food>>,
+    mut wall_query: Query<&mut Sprite, (With<Wall>, Without<Food>)>,
+    mut tile_query: Query<
+        (&mut Sprite, &Transform),
+        (With<GridTile>, Without<Wall>, Without<Food>),
+    >,
+    mut eye_query: Query<&mut Sprite, (With<Eye>, Without<GridTile>, Without<Wall>, Without<Food>)>,
+    mut portal_query: Query<
+        &mut Sprite,
+        (
+            With<Portal>,
+            Without<Eye>,
+            Without<GridTile>,
+            Without<Wall>,
+            Without<Food>,
+        ),
+    >,
+    mut border_query: Query<
+        &mut Sprite,
+        (
+            With<Border>,
+            Without<Portal>,
+            Without<Eye>,
+            Without<GridTile>,
+            Without<Wall>,
+            Without<Food>,
+        ),
+    >,
+) {
+    if !theme.is_changed() {
+        return;
+    }
+    clear_color.0 = theme.background;
+    for (mut sprite, value_timer, golden, rotten) in food_query.iter_mut() {
+        sprite.color = match (golden, rotten) {
+            (Some(_), _) => theme.golden_food,
+            (_, Some(_)) => theme.rotten_food,
+            _ => food_color(&theme, value_timer),
+        };
+    }
+    for mut sprite in wall_query.iter_mut() {
+        sprite.color = theme.wall;
+    }
+    for (mut sprite, transform) in tile_query.iter_mut() {
+        let (x, y) = grid_config.world_to_grid(transform.translation, &board);
+        sprite.color = theme.grid[((x + y) % 2) as usize];
+    }
+    for mut sprite in eye_query.iter_mut() {
+        sprite.color = theme.eyes;
+    }
+    for mut sprite in portal_query.iter_mut() {
+        sprite.color = theme.portal;
+    }
+    for mut sprite in border_query.iter_mut() {
+        sprite.color = theme.wall;
+    }
+}
+
+fn update_head_facing(
+    mut head_query: Query<(&Player, &Velocity, &mut Transform), Changed<Velocity>>,
+) {
+    for (player, velocity, mut transform) in head_query.iter_mut() {
+        let direction = match velocity.direction {
+            Direction::NONE => start_direction(*player),
+            direction => direction,
+        };
+        transform.rotation = facing_rotation(direction);
+    }
+}
+
+// Without foods from the level, the food starts one cell up and right of the head
+fn initial_food_cell((spawn_x, spawn_y): (i32, i32)) -> (i32, i32) {
+    (spawn_x + 1, spawn_y + 1)
+}
+
+// Foods placed by the level come first, then any others go to random free cells
+#[allow(clippy::too_many_arguments)]
+fn initialize_food(
+    mut commands: Commands,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    start_length: Res<StartLength>,
+    food_count: Res<FoodCount>,
+    level_layout: Res<LevelLayout>,
+    theme: Res<Theme>,
+    player_count: Res<PlayerCount>,
+    ai_enabled: Res<AiEnabled>,
+    start: Res<StartDirection>,
+    stress_test: Res<StressTest>,
+    mut rng: ResMut<GameRng>,
+) {
+    let start_cell = level_layout.start_cell(&grid_config, &board);
+    let mut fixed_cells: Vec<(i32, i32)> = if level_layout.foods.is_empty() {
+        vec![initial_food_cell(start_cell)]
+    } else {
+        level_layout.foods.clone()
+    };
+    let mut occupied: HashSet<(i32, i32)> = (0..snake_count(&player_count, &ai_enabled))
+        .map(Player)
+        .flat_map(|player| {
+            player_start_cells(
+                player,
+                &level_layout,
+                &grid_config,
+                &board,
+                &start,
+                start_length.0,
+                &stress_test,
+            )
+        })
+        .collect();
+    // No food under a snake, a stress test one covers most of the board
+    fixed_cells.retain(|cell| !occupied.contains(cell));
+    occupied.extend(level_layout.walls.iter().copied());
+    occupied.extend(level_layout.portals.iter().copied());
+    let color = food_color(&theme, &ValueTimer::new());
+    for &cell in &fixed_cells {
+        occupied.insert(cell);
+        spawn_food(&mut commands, &grid_config, &board, cell.into(), color);
+    }
+
+    for _ in fixed_cells.len()..food_count.0 {
+        let free = free_tiles(board.cols as i32, board.rows as i32, &occupied);
+        if free.is_empty() {
+            break;
+        }
+        let tile = free[rng.rng.gen_range(0..free.len())];
+        occupied.insert(tile);
+        spawn_food(&mut commands, &grid_config, &board, tile.into(), color);
+    }
+}
+
+fn spawn_food(
+    commands: &mut Commands,
+    grid_config: &GridConfig,
+    board: &Board,
+    pos: GridPos,
+    color: Color,
+) -> Entity {
+    let food_size = grid_config.head_size();
+    let translation = grid_config
+        .grid_to_world(pos.x, pos.y, board)
+        .extend(FOOD_LAYER);
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(Vec2::new(food_size, food_size)),
+                ..Default::default()
+            },
+            transform: Transform {
+                translation,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Food)
+        .insert(pos)
+        .insert(ValueTimer::new())
+        .id()
+}
+
+fn initialize_walls(
+    mut commands: Commands,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    level_layout: Res<LevelLayout>,
+    theme: Res<Theme>,
+    mut occupied: ResMut<OccupiedCells>,
+) {
+    for &(x, y) in &level_layout.walls {
+        occupied.add((x, y));
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color: theme.wall,
+                    custom_size: Some(grid_config.cell_size()),
+                    ..Default::default()
+                },
+                transform: Transform {
+                    translation: grid_config.grid_to_world(x, y, &board).extend(FOOD_LAYER),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(Wall)
+            .insert(GridPos::new(x, y));
+    }
+}
+
+// Both ends of the level's portal pair, each linked to the other
+fn initialize_portals(
+    mut commands: Commands,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    level_layout: Res<LevelLayout>,
+    theme: Res<Theme>,
+) {
+    let ends: Vec<Entity> = level_layout
+        .portals
+        .iter()
+        .map(|&(x, y)| {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: theme.portal,
+                        custom_size: Some(grid_config.cell_size()),
+                        ..Default::default()
+                    },
+                    transform: Transform {
+                        translation: grid_config.grid_to_world(x, y, &board).extend(FOOD_LAYER),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(GridPos::new(x, y))
+                .id()
+        })
+        .collect();
+    if let [first, second] = ends[..] {
+        commands.entity(first).insert(Portal { partner: second });
+        commands.entity(second).insert(Portal { partner: first });
+    }
+}
+
+fn setup_splash(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load(FONT_PATH);
+    for (value, font_size, top) in [
+        ("rusnake", 120., 35.),
+        ("a snake game in Rust", HUD_FONT_SIZE, 60.),
+    ] {
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        top: Val::Percent(top),
+                        left: Val::Percent(25.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    value,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(SplashText);
+    }
+    commands.insert_resource(SplashTimer(Timer::from_seconds(SPLASH_SECONDS, false)));
+}
+
+// The splash gives way to the menu when its time is up, or at once on Enter or Escape
+fn end_splash(
+    mut kb: ResMut<Input<KeyCode>>,
+    time: Res<Time>,
+    mut splash_timer: ResMut<SplashTimer>,
+    mut app_state: ResMut<State<AppState>>,
+) {
+    let skipped = kb.any_just_pressed([KeyCode::Return, KeyCode::Escape]);
+    if splash_timer.0.tick(time.delta()).finished() || skipped {
+        // The menu would start a round on the same Enter
+        kb.clear_just_pressed(KeyCode::Return);
+        app_state.set(AppState::Menu).unwrap();
+    }
+}
+
+fn cleanup_splash(mut commands: Commands, splash_query: Query<Entity, With<SplashText>>) {
+    for entity in splash_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<SplashTimer>();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn setup_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    player_count: Res<PlayerCount>,
+    ai_enabled: Res<AiEnabled>,
+    difficulty: Res<Difficulty>,
+    time_limit: Res<TimeLimit>,
+    objective: Res<Objective>,
+    collisions_enabled: Res<CollisionsEnabled>,
+) {
+    let font = asset_server.load(FONT_PATH);
+    let mut menu_line = |value: &str, font_size: f32, top: f32| {
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        top: Val::Percent(top),
+                        left: Val::Percent(30.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    value,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size,
+                        color: Color::WHITE,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(MenuText)
+            .id()
+    };
+    menu_line("rusnake", 80., 30.);
+    menu_line("Press Enter to Start", HUD_FONT_SIZE, 55.);
+    menu_line("Press O for Options", HUD_FONT_SIZE, 76.);
+    menu_line("Press R to watch the last round", HUD_FONT_SIZE, 83.);
+    let count_line = menu_line(
+        &player_count_label(&player_count, &ai_enabled),
+        HUD_FONT_SIZE,
+        62.,
+    );
+    let difficulty_line = menu_line(&difficulty_label(&difficulty), HUD_FONT_SIZE, 69.);
+    let mode_line = menu_line(
+        &mode_label(&time_limit, &objective, &collisions_enabled),
+        HUD_FONT_SIZE,
+        90.,
+    );
+    commands.entity(count_line).insert(PlayerCountText);
+    commands.entity(difficulty_line).insert(DifficultyText);
+    commands.entity(mode_line).insert(ModeText);
+}
+
+fn mode_label(
+    time_limit: &TimeLimit,
+    objective: &Objective,
+    collisions_enabled: &CollisionsEnabled,
+) -> String {
+    match (time_limit.0, objective.target, collisions_enabled.0) {
+        (_, _, false) => "Mode: Practice, no crashes (press Tab)".to_string(),
+        (Some(seconds), _, _) => format!("Mode: Time attack, {}s (press Tab)", seconds),
+        (None, 0, _) => "Mode: Classic (press Tab)".to_string(),
+        (None, target, _) => format!("Mode: Eat {} to win (press Tab)", target),
+    }
+}
+
+// Tab goes from the regular mode to a time attack against TIME_ATTACK_SECONDS, then to
+// eating OBJECTIVE_FOODS to win, then to practice and back. Not T, that's the theme key.
+fn choose_mode(
+    kb: Res<Input<KeyCode>>,
+    mut time_limit: ResMut<TimeLimit>,
+    mut objective: ResMut<Objective>,
+    mut collisions_enabled: ResMut<CollisionsEnabled>,
+    mut text_query: Query<&mut Text, With<ModeText>>,
+) {
+    if kb.just_pressed(KeyCode::Tab) {
+        let (limit, target, collisions) =
+            match (time_limit.0, objective.target, collisions_enabled.0) {
+                (_, _, false) => (None, 0, true),
+                (None, 0, _) => (Some(TIME_ATTACK_SECONDS), 0, true),
+                (Some(_), _, _) => (None, OBJECTIVE_FOODS, true),
+                (None, _, _) => (None, 0, false),
+            };
+        time_limit.0 = limit;
+        *objective = Objective::new(target);
+        collisions_enabled.0 = collisions;
+        for mut text in text_query.iter_mut() {
+            text.sections[0].value = mode_label(&time_limit, &objective, &collisions_enabled);
+        }
+    }
+}
+
+fn difficulty_label(difficulty: &Difficulty) -> String {
+    format!("Difficulty: {:?} (press D)", difficulty)
+}
+
+fn choose_difficulty(
+    kb: Res<Input<KeyCode>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut text_query: Query<&mut Text, With<DifficultyText>>,
+) {
+    if kb.just_pressed(KeyCode::D) {
+        *difficulty = difficulty.next();
+        for mut text in text_query.iter_mut() {
+            text.sections[0].value = difficulty_label(&difficulty);
+        }
+    }
+}
+
+fn player_count_label(player_count: &PlayerCount, ai_enabled: &AiEnabled) -> String {
+    let opponent = if ai_enabled.0 { " vs computer" } else { "" };
+    format!("Players: {}{} (press 1, 2 or C)", player_count.0, opponent)
+}
+
+// 1 and 2 pick how many snakes the next round has, C turns the computer opponent on or off
+fn choose_player_count(
+    kb: Res<Input<KeyCode>>,
+    mut player_count: ResMut<PlayerCount>,
+    mut ai_enabled: ResMut<AiEnabled>,
+    mut text_query: Query<&mut Text, With<PlayerCountText>>,
+) {
+    if kb.just_pressed(KeyCode::Key1) {
+        player_count.0 = 1;
+    } else if kb.just_pressed(KeyCode::Key2) {
+        player_count.0 = 2;
+    } else if kb.just_pressed(KeyCode::C) {
+        ai_enabled.0 = !ai_enabled.0;
+    }
+    if player_count.is_changed() || ai_enabled.is_changed() {
+        for mut text in text_query.iter_mut() {
+            text.sections[0].value = player_count_label(&player_count, &ai_enabled);
+        }
+    }
+}
+
+fn start_game(
+    kb: Res<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut playback: ResMut<Playback>,
+) {
+    if kb.just_pressed(KeyCode::Return) {
+        playback.recording = None;
+        app_state.set(AppState::InGame).unwrap();
+    }
+}
+
+// R plays the last recorded round again
+fn watch_replay(
+    kb: Res<Input<KeyCode>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut playback: ResMut<Playback>,
+) {
+    if !kb.just_pressed(KeyCode::R) {
+        return;
+    }
+    match Recording::load(REPLAY_PATH) {
+        Ok(recording) => {
+            playback.recording = Some(recording);
+            app_state.set(AppState::InGame).unwrap();
+        }
+        Err(error) => warn!("could not load {}: {}", REPLAY_PATH, error),
+    }
+}
+
+fn open_options(kb: Res<Input<KeyCode>>, mut app_state: ResMut<State<AppState>>) {
+    if kb.just_pressed(KeyCode::O) {
+        app_state.set(AppState::Options).unwrap();
+    }
+}
+
+// The options are menu text too, cleaned up along with it
+fn setup_options(mut commands: Commands, asset_server: Res<AssetServer>, settings: Res<Settings>) {
+    let text_style = TextStyle {
+        font: asset_server.load(FONT_PATH),
+        font_size: HUD_FONT_SIZE,
+        color: Color::WHITE,
+    };
+    let mut sections: Vec<TextSection> = ["Options\n\n".to_string()]
+        .into_iter()
+        .chain(options_lines(&settings, 0))
+        .chain(["\nUp/Down to choose, Left/Right to change, Escape to go back".to_string()])
+        .map(|value| TextSection {
+            value,
+            style: text_style.clone(),
+        })
+        .collect();
+    sections[0].style.font_size = 2. * HUD_FONT_SIZE;
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(25.),
+                    left: Val::Percent(25.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(MenuText)
+        .insert(OptionsText);
+}
+
+// One line per field, the selected one marked
+fn options_lines(settings: &Settings, selected: usize) -> impl Iterator<Item = String> + '_ {
+    OptionField::ALL
+        .iter()
+        .enumerate()
+        .map(move |(index, field)| {
+            let marker = if index == selected { "> " } else { "  " };
+            format!("{}{}\n", marker, settings.label(*field))
+        })
+}
+
+fn edit_options(
+    kb: Res<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    mut selected: Local<usize>,
+    mut app_state: ResMut<State<AppState>>,
+    mut text_query: Query<&mut Text, With<OptionsText>>,
+) {
+    let fields = OptionField::ALL.len();
+    let previous = *selected;
+    if kb.just_pressed(KeyCode::Up) {
+        *selected = (*selected + fields - 1) % fields;
+    } else if kb.just_pressed(KeyCode::Down) {
+        *selected = (*selected + 1) % fields;
+    } else if kb.just_pressed(KeyCode::Left) {
+        settings.adjust(OptionField::ALL[*selected], false);
+    } else if kb.just_pressed(KeyCode::Right) {
+        settings.adjust(OptionField::ALL[*selected], true);
+    } else if kb.just_pressed(KeyCode::Escape) {
+        // Not Enter, the menu would take the same press as the start of a round
+        app_state.set(AppState::Menu).unwrap();
+    }
+
+    for mut text in text_query.iter_mut() {
+        if settings.is_changed() || *selected != previous || text.is_added() {
+            for (section, line) in text.sections[1..]
+                .iter_mut()
+                .zip(options_lines(&settings, *selected))
+            {
+                section.value = line;
+            }
+        }
+    }
+}
+
+// Leaving the menu writes the options into the resources the next round starts from. A
+// level with its own board size keeps it, otherwise the difficulty may shrink it.
+#[allow(clippy::too_many_arguments)]
+fn apply_settings(
+    settings: Res<Settings>,
+    difficulty: Res<Difficulty>,
+    level_layout: Res<LevelLayout>,
+    mut board: ResMut<Board>,
+    mut boundary_mode: ResMut<BoundaryMode>,
+    mut self_collision_mode: ResMut<SelfCollisionMode>,
+    mut scoring_mode: ResMut<ScoringMode>,
+    collisions_enabled: Res<CollisionsEnabled>,
+    mut food_count: ResMut<FoodCount>,
+    mut start_interval: ResMut<StartInterval>,
+) {
+    let wanted = match difficulty.max_board() {
+        Some(max_board) => Board {
+            cols: settings.board.cols.min(max_board.cols),
+            rows: settings.board.rows.min(max_board.rows),
+        },
+        None => settings.board,
+    };
+    if level_layout.board().is_none() && *board != wanted {
+        *board = wanted;
+    }
+    // Practice has no walls around the board to crash into, the snake comes back on the
+    // other side instead
+    *boundary_mode = if collisions_enabled.0 {
+        settings.boundary_mode
+    } else {
+        BoundaryMode::Wrap
+    };
+    *self_collision_mode = settings.self_collision_mode;
+    *scoring_mode = settings.scoring_mode;
+    food_count.0 = settings.food_count;
+    start_interval.0 = settings.step_interval();
+}
+
+fn cleanup_menu(mut commands: Commands, menu_query: Query<Entity, With<MenuText>>) {
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+// Puts every per-round resource back to its starting value on each entry into play
+#[allow(clippy::too_many_arguments)]
+fn start_round(
+    mut commands: Commands,
+    clock: Res<GameClock>,
+    countdown_length: Res<CountdownLength>,
+    start_interval: Res<StartInterval>,
+    difficulty: Res<Difficulty>,
+    mut accumulator: ResMut<StepAccumulator>,
+    mut tick: ResMut<Tick>,
+    rng_seed: Res<RngSeed>,
+    mut rng: ResMut<GameRng>,
+    mut recording: ResMut<Recording>,
+    mut playback: ResMut<Playback>,
+    time_limit: Res<TimeLimit>,
+    mut time_remaining: ResMut<TimeRemaining>,
+    mut objective: ResMut<Objective>,
+) {
+    // A replay plays with the seed it was recorded with, any other round with the
+    // configured one or else a fresh one
+    let seed = match &playback.recording {
+        Some(recording) => recording.seed,
+        None => rng_seed.0.unwrap_or_else(rand::random),
+    };
+    *rng = GameRng::new(seed);
+    *recording = Recording::new(seed);
+    playback.step = 0;
+
+    // Restart the step clock so the first step of the round waits a full step
+    *accumulator = StepAccumulator::new(clock.seconds);
+    tick.allowed = false;
+    tick.due = 0;
+    commands.insert_resource(Score { value: 0 });
+    commands.insert_resource(Combo::new());
+    commands.insert_resource(Dash::default());
+    commands.insert_resource(difficulty.step_interval(start_interval.0));
+    commands.insert_resource(GameState::Running);
+    commands.insert_resource(Paused(false));
+    commands.insert_resource(Countdown::new(clock.seconds, countdown_length.0));
+    // Set right away, the time left from the last round must not end this one
+    *time_remaining = TimeRemaining::new(time_limit.0, clock.seconds);
+    objective.eaten = 0;
+}
+
+// Ends a time attack round with the score it got so far once its time is up
+fn count_down_time(
+    clock: Res<GameClock>,
+    paused: Res<Paused>,
+    countdown: Res<Countdown>,
+    mut game_state: ResMut<GameState>,
+    mut time_remaining: ResMut<TimeRemaining>,
+    mut game_over: EventWriter<GameOver>,
+) {
+    let running =
+        *game_state == GameState::Running && !paused.0 && !countdown.running(clock.seconds);
+    time_remaining.count(clock.seconds, running);
+    if running && time_remaining.is_up() {
+        info!("time is up");
+        *game_state = GameState::GameOver;
+        game_over.send(GameOver {
+            won: false,
+            crashed: false,
+        });
+    }
+}
+
+// Once the countdown runs out, snakes nobody turned yet set off on their own
+fn finish_countdown(
+    clock: Res<GameClock>,
+    mut countdown: ResMut<Countdown>,
+    mut head_query: Query<(&Player, &Velocity, &mut NextDirection), With<Head>>,
+) {
+    if countdown.started || countdown.running(clock.seconds) {
+        return;
+    }
+    countdown.started = true;
+    for (player, velocity, mut next_direction) in head_query.iter_mut() {
+        if velocity.direction == Direction::NONE && next_direction.queue.is_empty() {
+            next_direction.push(start_direction(*player), Direction::NONE);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn cleanup_game(
+    mut commands: Commands,
+    mut game_state: ResMut<GameState>,
+    mut paused: ResMut<Paused>,
+    mut occupied: ResMut<OccupiedCells>,
+    entity_query: Query<
+        Entity,
+        Or<(
+            With<Head>,
+            With<Tail>,
+            With<Food>,
+            With<Wall>,
+            With<Portal>,
+            With<EdgeGlow>,
+        )>,
+    >,
+) {
+    for entity in entity_query.iter() {
+        // Recursive so the head takes its eyes along
+        commands.entity(entity).despawn_recursive();
+    }
+    occupied.clear();
+    commands.remove_resource::<DyingTimer>();
+    // Hide the round's overlays while outside of play
+    *game_state = GameState::Running;
+    paused.0 = false;
+}
+
+// After a game over or a win, the restart key or Enter plays again and Escape goes back to the menu.
+// Restarting the state runs cleanup_game and the InGame setup again. Only a fresh press
+// counts, a key held down from the round that just ended doesn't restart it.
+#[allow(clippy::too_many_arguments)]
+fn reset_game(
+    kb: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    clock: Res<GameClock>,
+    game_state: Res<GameState>,
+    dying_timer: Option<Res<DyingTimer>>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_reset: EventWriter<GameReset>,
+    mut ended_at: Local<Option<f64>>,
+) {
+    if *game_state == GameState::Running {
+        *ended_at = None;
+        return;
+    }
+    let ended_at = *ended_at.get_or_insert(clock.seconds);
+    // Keys pressed while the snakes are still dying, or right as the round ends, are ignored
+    let settled = clock.seconds - ended_at >= RESTART_DELAY_SECONDS && dying_timer.is_none();
+
+    if settled && kb.any_just_pressed([key_bindings.restart, KeyCode::Return]) {
+        app_state.restart().unwrap();
+        game_reset.send(GameReset);
+    } else if kb.just_pressed(KeyCode::Escape) {
+        app_state.set(AppState::Menu).unwrap();
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn get_next_move(
+    kb: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    input_mode: Res<InputMode>,
+    u_turn_guard: Res<UTurnGuard>,
+    paused: Res<Paused>,
+    tick: Res<Tick>,
+    boundary_mode: Res<BoundaryMode>,
+    board: Res<Board>,
+    occupied: Res<OccupiedCells>,
+    food_query: Query<(&GridPos, Option<&Rotten>), With<Food>>,
+    portal_query: Query<(&GridPos, &Portal)>,
+    mut query: Query<
+        (
+            &Player,
+            &Snake,
+            &Velocity,
+            &mut NextDirection,
+            &mut Controller,
+            &GridPos,
+        ),
+        With<Head>,
+    >,
+) {
+    // The arrow keys pick from the pause menu then, they don't queue turns
+    if paused.0 {
+        return;
+    }
+    for (player, snake, velocity, mut next_direction, mut controller, head_pos) in query.iter_mut()
+    {
+        // Every cell a head can crash into on the next step is taken, and so is rotten food
+        let is_safe = |direction: Direction| {
+            let next = next_head_cell(*head_pos, direction, &board, *boundary_mode);
+            let next = portal_query
+                .iter()
+                .find(|(portal_pos, _)| **portal_pos == next)
+                .and_then(|(_, portal)| portal_query.get(portal.partner).ok())
+                .map_or(next, |(partner_pos, _)| *partner_pos);
+            board.contains(next.cell())
+                && !occupied.contains(next.cell())
+                && !food_query
+                    .iter()
+                    .any(|(food_pos, rotten)| rotten.is_some() && *food_pos == next)
+        };
+        // A pre-grown snake that hasn't moved yet must not turn back into its own body
+        let pre_grown = snake.segments.len() > 1;
+        let applied = if velocity.direction == Direction::NONE && pre_grown {
+            start_direction(*player)
+        } else {
+            velocity.direction
+        };
+        let context = InputContext {
+            keyboard: &kb,
+            gamepad_buttons: &gamepad_buttons,
+            gamepad_axes: &gamepad_axes,
+            gamepad: gamepads.iter().next().copied(),
+            input_mode: *input_mode,
+            tick: tick.allowed,
+            head: *head_pos,
+            food: food_query
+                .iter()
+                .filter(|(_, rotten)| rotten.is_none())
+                .map(|(food_pos, _)| *food_pos)
+                .min_by_key(|food| (food.x - head_pos.x).pow(2) + (food.y - head_pos.y).pow(2)),
+            heading: velocity.direction,
+            is_safe: &is_safe,
+        };
+        if let Some(direction) = controller.source.next_direction(&context) {
+            if u_turn_guard.0 && next_direction.doubles_back(direction, applied) {
+                debug!("dropped a turn doubling back for player {}", player.0);
+            } else {
+                next_direction.push(direction, applied);
+            }
+        }
+    }
+}
+
+// The dash key asks for a dash on the next step, once the player's cooldown is over. A
+// single player dashes with either key. Replays don't record dashes, so none are taken
+// while one plays.
+#[allow(clippy::too_many_arguments)]
+fn request_dash(
+    kb: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    clock: Res<GameClock>,
+    player_count: Res<PlayerCount>,
+    ai_enabled: Res<AiEnabled>,
+    playback: Res<Playback>,
+    mut dash: ResMut<Dash>,
+    head_query: Query<&Player, With<Head>>,
+) {
+    if playback.recording.is_some() {
+        return;
+    }
+    for player in head_query.iter() {
+        // The computer's snake doesn't dash
+        if ai_enabled.0 && player.0 == 1 {
+            continue;
+        }
+        let pressed = if player_count.0 == 1 {
+            kb.any_just_pressed(key_bindings.dash)
+        } else {
+            kb.just_pressed(key_bindings.dash[player.0 as usize % 2])
+        };
+        if pressed && dash.ready(*player, clock.seconds) {
+            dash.requested.insert(*player);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn move_snake(
+    boundary_mode: Res<BoundaryMode>,
+    board: Res<Board>,
+    clock: Res<GameClock>,
+    mut dash: ResMut<Dash>,
+    mut occupied: ResMut<OccupiedCells>,
+    mut head_query: Query<
+        (
+            &Player,
+            &Snake,
+            &mut Velocity,
+            &mut NextDirection,
+            &mut GridPos,
+        ),
+        With<Head>,
+    >,
+    tick: Res<Tick>,
+    mut body_query: Query<&mut GridPos, (With<Tail>, Without<Head>)>,
+    portal_query: Query<(&GridPos, &Portal), (Without<Head>, Without<Tail>)>,
+) {
+    if !tick.allowed {
+        return;
+    }
+    for (player, snake, mut velocity, mut next_direction, mut head_pos) in head_query.iter_mut() {
+        let heading = velocity.direction;
+        if let Some(direction) = next_direction.queue.pop_front() {
+            velocity.direction = direction;
+        }
+        let dashing = dash.requested.remove(player);
+        // Until the first turn the snake stands still, the body must not fold onto the head
+        if velocity.direction == Direction::NONE {
+            continue;
+        }
+        // Onto one end of a portal and out of the other, still going the same way. The
+        // body follows on its own through the cells the head left.
+        let step_from = |pos: GridPos| {
+            let next = next_head_cell(pos, velocity.direction, &board, *boundary_mode);
+            portal_query
+                .iter()
+                .find(|(portal_pos, _)| **portal_pos == next)
+                .and_then(|(_, portal)| portal_query.get(portal.partner).ok())
+                .map_or(next, |(partner_pos, _)| *partner_pos)
+        };
+        let mut path = vec![step_from(*head_pos)];
+        // A dash goes on to a second cell, but never over anything in the first one and
+        // never doubling back into the snake's own neck
+        if dashing
+            && velocity.direction != heading.opposite()
+            && board.contains(path[0].cell())
+            && !occupied.contains(path[0].cell())
+        {
+            path.push(step_from(path[0]));
+            dash.ready_at
+                .insert(*player, clock.seconds + DASH_COOLDOWN_SECONDS);
+        }
+
+        // Segments spawned this step aren't in the world yet, they join on the next one
+        let mut cells = vec![*head_pos];
+        let mut body = Vec::new();
+        for entity in snake.segments.iter().skip(1) {
+            if let Ok(pos) = body_query.get(*entity) {
+                cells.push(*pos);
+                body.push(*entity);
+            }
+        }
+        let followed = follow_head(&path, &cells);
+        *head_pos = followed[0];
+        for (entity, cell) in body.iter().zip(&followed[1..]) {
+            if let Ok(mut pos) = body_query.get_mut(*entity) {
+                *pos = *cell;
+            }
+        }
+        // Only the cells the tail left and the ones the head moved onto change hands
+        let moved = path.len().min(cells.len());
+        for pos in &cells[cells.len() - moved..] {
+            occupied.remove(pos.cell());
+        }
+        for pos in &followed[..moved] {
+            occupied.add(pos.cell());
+        }
+    }
+}
+
+// Where a snake on `segments`, head first, ends up after its head went along `path` in
+// that order. The body keeps the same length and trails the head through every cell it
+// passed, however many that were in one step.
+fn follow_head(path: &[GridPos], segments: &[GridPos]) -> Vec<GridPos> {
+    path.iter()
+        .rev()
+        .chain(segments)
+        .take(segments.len())
+        .copied()
+        .collect()
+}
+
+// During a replay each snake turns exactly as recorded, whatever the input said
+fn play_back_moves(
+    mut playback: ResMut<Playback>,
+    mut head_query: Query<(&Player, &mut NextDirection), With<Head>>,
+) {
+    let step = playback.step;
+    let moves = match &playback.recording {
+        Some(recording) => recording.moves.get(step).cloned().unwrap_or_default(),
+        None => return,
+    };
+    for (player, mut next_direction) in head_query.iter_mut() {
+        if let Some(&direction) = moves.get(player.0 as usize) {
+            next_direction.queue.clear();
+            next_direction.queue.push_back(direction);
+        }
+    }
+    playback.step += 1;
+}
+
+fn record_moves(
+    mut recording: ResMut<Recording>,
+    head_query: Query<(&Player, &Velocity), With<Head>>,
+) {
+    let mut moves: Vec<(Player, Direction)> = head_query
+        .iter()
+        .map(|(player, velocity)| (*player, velocity.direction))
+        .collect();
+    moves.sort_by_key(|(player, _)| *player);
+    recording
+        .moves
+        .push(moves.into_iter().map(|(_, direction)| direction).collect());
+}
+
+// Steps a head going `direction` can take before it leaves the board, 0 when the next
+// one takes it off. None for a head that isn't moving.
+fn steps_to_edge(pos: GridPos, direction: Direction, board: &Board) -> Option<i32> {
+    match direction {
+        Direction::UP => Some(board.rows as i32 - 1 - pos.y),
+        Direction::DOWN => Some(pos.y),
+        Direction::LEFT => Some(pos.x),
+        Direction::RIGHT => Some(board.cols as i32 - 1 - pos.x),
+        Direction::NONE => None,
+    }
+}
+
+// The cell a head on `pos` moves onto with one step `direction`, wrapped back onto the
+// board in wrap mode
+fn next_head_cell(
+    pos: GridPos,
+    direction: Direction,
+    board: &Board,
+    boundary_mode: BoundaryMode,
+) -> GridPos {
+    let velocity = direction.velocity();
+    let next = GridPos::new(pos.x + velocity.x as i32, pos.y + velocity.y as i32);
+    match boundary_mode {
+        BoundaryMode::Wrap => board.wrap(next),
+        BoundaryMode::Wall => next,
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn decay_food_value(
+    tick: Res<Tick>,
+    food_decay: Res<FoodDecay>,
+    theme: Res<Theme>,
+    mut food_query: Query<
+        (&mut ValueTimer, &mut Sprite),
+        (With<Food>, Without<Golden>, Without<Rotten>),
+    >,
+) {
+    if tick.allowed && food_decay.0 {
+        for (mut value_timer, mut sprite) in food_query.iter_mut() {
+            value_timer.tick();
+            sprite.color = food_color(&theme, &value_timer);
+        }
+    }
+}
+
+// Food fades from the theme's fresh color towards its stale one as its value drops
+fn food_color(theme: &Theme, value_timer: &ValueTimer) -> Color {
+    let worth = (value_timer.value() - FOOD_MIN_VALUE) / (FOOD_START_VALUE - FOOD_MIN_VALUE);
+    theme.food_color(worth)
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn eat_food(
+    mut commands: Commands,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    body_query: Query<&GridPos, Without<Food>>,
+    mut food_query: Query<
+        (
+            Entity,
+            &mut GridPos,
+            &mut ValueTimer,
+            &mut Sprite,
+            Option<&Golden>,
+            Option<&Rotten>,
+        ),
+        With<Food>,
+    >,
+    mut snake_query: Query<(&Player, &Snake, &mut LateSpawn), With<Head>>,
+    mut step_interval: ResMut<StepInterval>,
+    mut game_state: ResMut<GameState>,
+    mut food_eaten: EventWriter<FoodEaten>,
+    mut game_over: EventWriter<GameOver>,
+    golden_chance: Res<GoldenFoodChance>,
+    rotten_chance: Res<RottenFoodChance>,
+    occupied_cells: Res<OccupiedCells>,
+    level_layout: Res<LevelLayout>,
+    theme: Res<Theme>,
+    mut rng: ResMut<GameRng>,
+) {
+    // Every snake counts as taken, whichever one is eating, and so does every wall. Food
+    // never goes on a portal either.
+    let mut blocked: HashSet<(i32, i32)> = occupied_cells.cells().collect();
+    blocked.extend(level_layout.portals.iter().copied());
+    let columns = board.cols as i32;
+    let rows = board.rows as i32;
+
+    for (player, snake, mut tail_spawner) in snake_query.iter_mut() {
+        let head_pos = body_query.get(snake.segments[0]).unwrap();
+        // Rotten food isn't eaten, collision_check deals with a head running onto it
+        let eaten = food_query
+            .iter()
+            .find_map(|(entity, food_pos, _, _, _, rotten)| {
+                (food_pos == head_pos && rotten.is_none()).then_some(entity)
+            });
+        let eaten = match eaten {
+            Some(entity) => entity,
+            None => continue,
+        };
+
+        // Cells already holding one of the other foods are not free for the eaten one
+        let mut occupied = blocked.clone();
+        for (entity, food_pos, _, _, _, _) in food_query.iter() {
+            if entity != eaten {
+                occupied.insert(food_pos.cell());
+            }
+        }
+        let golden_on_board = food_query
+            .iter()
+            .any(|(_, _, _, _, golden, _)| golden.is_some());
+        let rotten_on_board = food_query
+            .iter()
+            .any(|(_, _, _, _, _, rotten)| rotten.is_some());
+
+        let (_, mut food_pos, mut value_timer, mut food_sprite, golden, _) =
+            food_query.get_mut(eaten).unwrap();
+        let golden = golden.is_some();
+        let mut points = value_timer.value().floor() as u32;
+        if golden {
+            points += GOLDEN_FOOD_BONUS;
+        }
+        food_eaten.send(FoodEaten {
+            player: *player,
+            cell: food_pos.cell(),
+            points,
+            golden,
+        });
+        step_interval.speed_up();
+        value_timer.reset();
+        if !golden {
+            food_sprite.color = food_color(&theme, &value_timer);
+        }
+
+        // The growth goes to the snake that ate
+        let last_entity = snake.segments.last().unwrap();
+        if let Ok(last_pos) = body_query.get(*last_entity) {
+            tail_spawner.pending += if golden { GOLDEN_FOOD_GROWTH } else { 1 };
+            tail_spawner.cell = *last_pos;
+            debug!(
+                "player {} grows from {:?}, {} segments to come",
+                player.0,
+                last_pos.cell(),
+                tail_spawner.pending
+            );
+        }
+
+        let mut free = free_tiles(columns, rows, &occupied);
+        if free_tiles(columns, rows, &blocked).is_empty() {
+            *game_state = GameState::Victory;
+            game_over.send(GameOver {
+                won: true,
+                crashed: false,
+            });
+        } else if golden || free.is_empty() {
+            // Golden food is a one-off, and with every cell the snakes don't cover already
+            // holding food a regular one isn't needed either
+            commands.entity(eaten).despawn();
+        } else {
+            let rng = &mut rng.rng;
+            *food_pos = free.swap_remove(rng.gen_range(0..free.len())).into();
+
+            if !golden_on_board && !free.is_empty() && rng.gen_bool(golden_chance.0) {
+                let cell = free.swap_remove(rng.gen_range(0..free.len()));
+                let golden_food = spawn_food(
+                    &mut commands,
+                    &grid_config,
+                    &board,
+                    cell.into(),
+                    theme.golden_food,
+                );
+                commands
+                    .entity(golden_food)
+                    .insert(Golden)
+                    .insert(Lifetime::new(GOLDEN_FOOD_TICKS));
+            }
+            if !rotten_on_board && !free.is_empty() && rng.gen_bool(rotten_chance.0) {
+                let cell = free.swap_remove(rng.gen_range(0..free.len()));
+                let rotten_food = spawn_food(
+                    &mut commands,
+                    &grid_config,
+                    &board,
+                    cell.into(),
+                    theme.rotten_food,
+                );
+                commands
+                    .entity(rotten_food)
+                    .insert(Rotten)
+                    .insert(Lifetime::new(ROTTEN_FOOD_TICKS));
+            }
+        }
+    }
+}
+
+fn award_food_score(
+    mut food_eaten: EventReader<FoodEaten>,
+    scoring_mode: Res<ScoringMode>,
+    mut score: ResMut<Score>,
+    mut combo: ResMut<Combo>,
+) {
+    for event in food_eaten.iter() {
+        if *scoring_mode == ScoringMode::Points {
+            combo.eat();
+            score.value += event.points * combo.multiplier;
+        }
+    }
+}
+
+// Scoring by length, the score is every snake's segments behind its head. It follows the
+// snakes as they grow or are bitten short.
+fn score_by_length(
+    scoring_mode: Res<ScoringMode>,
+    snake_query: Query<&Snake>,
+    mut score: ResMut<Score>,
+) {
+    if *scoring_mode != ScoringMode::Length {
+        return;
+    }
+    let length: usize = snake_query
+        .iter()
+        .map(|snake| snake.segments.len().saturating_sub(1))
+        .sum();
+    // Only written on a change, so the HUD isn't updated every frame
+    if score.value != length as u32 {
+        score.value = length as u32;
+    }
+}
+
+// Wins the round once the objective's foods are eaten. A crash on the same step has
+// already ended it.
+fn count_objective_foods(
+    mut food_eaten: EventReader<FoodEaten>,
+    mut objective: ResMut<Objective>,
+    mut game_state: ResMut<GameState>,
+    mut game_over: EventWriter<GameOver>,
+) {
+    for _ in food_eaten.iter() {
+        objective.eaten += 1;
+        if objective.reached() && *game_state == GameState::Running {
+            *game_state = GameState::Victory;
+            game_over.send(GameOver {
+                won: true,
+                crashed: false,
+            });
+        }
+    }
+}
+
+fn decay_combo(tick: Res<Tick>, mut combo: ResMut<Combo>) {
+    if tick.allowed {
+        combo.tick();
+    }
+}
+
+// Golden and rotten food disappear once their time is up
+fn expire_timed_food(
+    mut commands: Commands,
+    tick: Res<Tick>,
+    mut timed_query: Query<(Entity, &mut Lifetime), With<Food>>,
+) {
+    if tick.allowed {
+        for (entity, mut lifetime) in timed_query.iter_mut() {
+            lifetime.tick();
+            if lifetime.expired() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+// Every tile on the board the food may be placed on, in one pass over the board
+fn free_tiles(columns: i32, rows: i32, occupied: &HashSet<(i32, i32)>) -> Vec<(i32, i32)> {
+    (0..columns)
+        .flat_map(|x| (0..rows).map(move |y| (x, y)))
+        .filter(|tile| !occupied.contains(tile))
+        .collect()
+}
+
+fn update_score_text(
+    score: Res<Score>,
+    high_score: Res<HighScore>,
+    snake_query: Query<(&Player, &Snake)>,
+    changed_snakes: Query<(), Changed<Snake>>,
+    mut text_query: Query<&mut Text, With<ScoreText>>,
+) {
+    if score.is_changed() || high_score.is_changed() || !changed_snakes.is_empty() {
+        for mut text in text_query.iter_mut() {
+            text.sections[1].value = score.value.to_string();
+            text.sections[3].value = snakes_by_player(&snake_query)
+                .iter()
+                .map(|(_, segments)| segments.len().to_string())
+                .collect::<Vec<String>>()
+                .join(" / ");
+            text.sections[5].value = high_score.value.to_string();
+        }
+    }
+}
+
+fn update_time_text(
+    time_remaining: Res<TimeRemaining>,
+    mut text_query: Query<&mut Text, With<ScoreText>>,
+) {
+    let value = match time_remaining.label() {
+        Some(label) => format!("  Time: {}", label),
+        None => String::new(),
+    };
+    for mut text in text_query.iter_mut() {
+        // Only touched once a second, the text isn't laid out again every frame
+        if text.sections[6].value != value {
+            text.sections[6].value = value.clone();
+        }
+    }
+}
+
+fn update_objective_text(
+    objective: Res<Objective>,
+    mut text_query: Query<&mut Text, With<ScoreText>>,
+) {
+    if !objective.is_changed() {
+        return;
+    }
+    let value = match objective.label() {
+        Some(label) => format!("  Eaten: {}", label),
+        None => String::new(),
+    };
+    for mut text in text_query.iter_mut() {
+        text.sections[8].value = value.clone();
+    }
+}
+
+// The dash cooldown of every snake, first player first
+fn update_dash_text(
+    clock: Res<GameClock>,
+    dash: Res<Dash>,
+    head_query: Query<&Player, With<Head>>,
+    mut text_query: Query<&mut Text, With<ScoreText>>,
+) {
+    let mut players: Vec<Player> = head_query.iter().copied().collect();
+    if players.is_empty() {
+        return;
+    }
+    players.sort();
+    let labels: Vec<String> = players
+        .iter()
+        .map(|player| dash.label(*player, clock.seconds))
+        .collect();
+    let value = format!("  Dash: {}", labels.join(" / "));
+    for mut text in text_query.iter_mut() {
+        // Only touched once a second, the text isn't laid out again every frame
+        if text.sections[10].value != value {
+            text.sections[10].value = value.clone();
+        }
+    }
+}
+
+fn update_practice_text(
+    collisions_enabled: Res<CollisionsEnabled>,
+    mut text_query: Query<&mut Text, With<ScoreText>>,
+) {
+    if !collisions_enabled.is_changed() {
+        return;
+    }
+    let value = if collisions_enabled.0 {
+        ""
+    } else {
+        "  Practice"
+    };
+    for mut text in text_query.iter_mut() {
+        text.sections[9].value = value.to_string();
+    }
+}
+
+fn update_combo_text(combo: Res<Combo>, mut text_query: Query<&mut Text, With<ScoreText>>) {
+    if !combo.is_changed() {
+        return;
+    }
+    let value = match combo.multiplier {
+        1 => String::new(),
+        multiplier => format!("  Combo: x{}", multiplier),
+    };
+    for mut text in text_query.iter_mut() {
+        if text.sections[7].value != value {
+            text.sections[7].value = value.clone();
+        }
+    }
+}
+
+// Feedback for the end of a round: the crash sound, and keeping a new best score on disk
+// with a toast for it
+fn finish_round(
+    mut game_over: EventReader<GameOver>,
+    score: Res<Score>,
+    mut high_score: ResMut<HighScore>,
+    sounds: Res<Sounds>,
+    muted: Res<Muted>,
+    audio: Res<Audio>,
+    mut toasts: EventWriter<Toast>,
+) {
+    for event in game_over.iter() {
+        if !event.won && !muted.0 {
+            audio.play(sounds.crash.clone());
+        }
+        if score.value > high_score.value {
+            high_score.value = score.value;
+            toasts.send(Toast::top("New high score!", HIGH_SCORE_TOAST_SECONDS));
+            if let Err(error) = high_score.save(HIGH_SCORE_PATH) {
+                warn!("could not save the high score: {}", error);
+            }
+        }
+    }
+}
+
+// A text for each toast sent, any system can put a message on screen this way
+fn show_toasts(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut toasts: EventReader<Toast>,
+) {
+    for toast in toasts.iter() {
+        let top = match toast.position {
+            ToastPosition::Center => Val::Percent(45.),
+            ToastPosition::Top => Val::Percent(10.),
+        };
+        // A clear row across the whole window with the text in the middle of it
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        top,
+                        left: Val::Px(0.),
+                        ..Default::default()
+                    },
+                    size: Size::new(Val::Percent(100.), Val::Auto),
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                color: UiColor(Color::NONE),
+                ..Default::default()
+            })
+            .with_children(|row| {
+                row.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        toast.text.clone(),
+                        TextStyle {
+                            font: asset_server.load(FONT_PATH),
+                            font_size: HUD_FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                        TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            vertical: VerticalAlign::Center,
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(ToastText::new(toast.seconds));
+            });
+    }
+}
+
+fn fade_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut toast_query: Query<(Entity, &mut ToastText, &mut Text, Option<&Parent>)>,
+) {
+    for (entity, mut toast, mut text, row) in toast_query.iter_mut() {
+        toast.timer.tick(time.delta());
+        if toast.timer.finished() {
+            let row = row.map_or(entity, |row| row.0);
+            commands.entity(row).despawn_recursive();
+            continue;
+        }
+        let opacity = toast.opacity(TOAST_FADE_SECONDS);
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(opacity);
+        }
+    }
+}
+
+// Keeps each finished round on disk for R in the menu, unless it was a replay already
+fn save_recording(
+    mut game_over: EventReader<GameOver>,
+    recording: Res<Recording>,
+    playback: Res<Playback>,
+) {
+    for _ in game_over.iter() {
+        if playback.recording.is_some() {
+            continue;
+        }
+        if let Err(error) = recording.save(REPLAY_PATH) {
+            warn!("could not save the replay: {}", error);
+        }
+    }
+}
+
+// A fading copy of each eaten food, left behind where it was eaten. Only a sprite, the
+// food itself has already moved on.
+fn spawn_food_ghost(
+    mut commands: Commands,
+    mut food_eaten: EventReader<FoodEaten>,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    theme: Res<Theme>,
+) {
+    let food_size = grid_config.head_size();
+    for event in food_eaten.iter() {
+        let (x, y) = event.cell;
+        let color = if event.golden {
+            theme.golden_food
+        } else {
+            theme.food
+        };
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(food_size, food_size)),
+                    ..Default::default()
+                },
+                // Under the food, so a new food placed on the same cell stays in sight
+                transform: Transform::from_translation(
+                    grid_config
+                        .grid_to_world(x, y, &board)
+                        .extend((GRID_LAYER + FOOD_LAYER) / 2.),
+                ),
+                ..Default::default()
+            })
+            .insert(FadeEffect::new(FOOD_FADE_SECONDS));
+    }
+}
+
+fn fade_out(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut fade_query: Query<(Entity, &mut FadeEffect, &mut Sprite, &mut Transform)>,
+) {
+    for (entity, mut fade, mut sprite, mut transform) in fade_query.iter_mut() {
+        fade.timer.tick(time.delta());
+        if fade.timer.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let remaining = fade.remaining();
+        sprite.color.set_a(remaining);
+        transform.scale = Vec3::splat(remaining);
+    }
+}
+
+// The joint is a child, so it scales along with its segment
+fn grow_in(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut grow_query: Query<(Entity, &mut GrowIn, &mut Transform)>,
+) {
+    for (entity, mut grow, mut transform) in grow_query.iter_mut() {
+        grow.timer.tick(time.delta());
+        transform.scale = Vec3::splat(grow.progress());
+        if grow.timer.finished() {
+            commands.entity(entity).remove::<GrowIn>();
+        }
+    }
+}
+
+fn start_eat_flash(
+    mut commands: Commands,
+    mut food_eaten: EventReader<FoodEaten>,
+    head_query: Query<(Entity, &Player), With<Head>>,
+) {
+    for event in food_eaten.iter() {
+        for (entity, player) in head_query.iter() {
+            if *player == event.player {
+                commands
+                    .entity(entity)
+                    .insert(EatFlash::new(EAT_FLASH_SECONDS));
+            }
+        }
+    }
+}
+
+// One warning bar inside each side of the board, hidden until a head nears that side
+fn initialize_edge_glows(mut commands: Commands, board: Res<Board>, grid_config: Res<GridConfig>) {
+    let shown = board.win_size(&grid_config, 0.);
+    let width = grid_config.shorter_side() * EDGE_GLOW_WIDTH;
+    for side in [
+        Direction::UP,
+        Direction::DOWN,
+        Direction::LEFT,
+        Direction::RIGHT,
+    ] {
+        let (size, offset) = match side {
+            Direction::UP | Direction::DOWN => (
+                Vec2::new(shown.w, width),
+                Vec2::new(0., (shown.h - width) / 2.),
+            ),
+            _ => (
+                Vec2::new(width, shown.h),
+                Vec2::new((shown.w - width) / 2., 0.),
+            ),
+        };
+        let offset = match side {
+            Direction::DOWN | Direction::LEFT => -offset,
+            _ => offset,
+        };
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    custom_size: Some(size),
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(
+                    offset.extend((FOOD_LAYER + SNAKE_LAYER) / 2.),
+                ),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(EdgeGlow { side });
+    }
+}
+
+// In wrap mode, lights the side of the board a head is about to cross, brighter the
+// closer it gets, so coming out on the opposite side isn't a surprise
+fn glow_wrap_edges(
+    boundary_mode: Res<BoundaryMode>,
+    board: Res<Board>,
+    theme: Res<Theme>,
+    head_query: Query<(&GridPos, &Velocity), With<Head>>,
+    mut edge_query: Query<(&EdgeGlow, &mut Sprite, &mut Visibility)>,
+) {
+    for (edge, mut sprite, mut visibility) in edge_query.iter_mut() {
+        let strength = match *boundary_mode {
+            BoundaryMode::Wrap => head_query
+                .iter()
+                .filter(|(_, velocity)| velocity.direction == edge.side)
+                .filter_map(|(pos, velocity)| steps_to_edge(*pos, velocity.direction, &board))
+                .map(|steps| 1. - steps as f32 / EDGE_GLOW_CELLS as f32)
+                .fold(0., f32::max),
+            BoundaryMode::Wall => 0.,
+        };
+        visibility.is_visible = strength > 0.;
+        let mut color = theme.flash;
+        color.set_a(EDGE_GLOW_ALPHA * strength);
+        sprite.color = color;
+    }
+}
+
+// Blends a flashing head back to its own color. That color is worked out every frame,
+// so a theme change or the snake growing meanwhile shows once the flash is over.
+fn flash_head(
+    mut commands: Commands,
+    time: Res<Time>,
+    theme: Res<Theme>,
+    mut head_query: Query<(Entity, &Player, &Snake, &mut EatFlash, &mut Sprite), With<Head>>,
+) {
+    for (entity, player, snake, mut flash, mut sprite) in head_query.iter_mut() {
+        flash.timer.tick(time.delta());
+        let own = theme.player_segment_color(*player, 0, snake.segments.len());
+        sprite.color = theme.flashed(own, flash.remaining());
+        if flash.timer.finished() {
+            commands.entity(entity).remove::<EatFlash>();
+        }
+    }
+}
+
+fn start_dying(mut commands: Commands, mut game_over: EventReader<GameOver>) {
+    for event in game_over.iter() {
+        if !event.won {
+            commands.insert_resource(DyingTimer(Timer::from_seconds(DEATH_SECONDS, false)));
+        }
+    }
+}
+
+// Turns the snakes of a lost round red and fades them out, eyes and all. Each joint takes
+// its segment's color in shape_snake. The entities stay until the round is cleaned up.
+#[allow(clippy::type_complexity)]
+fn animate_death(
+    mut commands: Commands,
+    time: Res<Time>,
+    theme: Res<Theme>,
+    dying_timer: Option<ResMut<DyingTimer>>,
+    mut sprite_query: Query<
+        (&mut Sprite, &mut Visibility, Option<&Eye>),
+        Or<(With<Head>, With<Tail>, With<Eye>)>,
+    >,
+) {
+    let mut dying_timer = match dying_timer {
+        Some(dying_timer) => dying_timer,
+        None => return,
+    };
+    dying_timer.0.tick(time.delta());
+    let finished = dying_timer.0.finished();
+    for (mut sprite, mut visibility, eye) in sprite_query.iter_mut() {
+        sprite.color = if eye.is_some() {
+            theme.eyes
+        } else {
+            theme.dying
+        };
+        sprite.color.set_a(dying_timer.0.percent_left());
+        visibility.is_visible = !finished;
+    }
+    if finished {
+        commands.remove_resource::<DyingTimer>();
+    }
+}
+
+fn start_shake(
+    mut commands: Commands,
+    grid_config: Res<GridConfig>,
+    mut game_over: EventReader<GameOver>,
+) {
+    if game_over.iter().any(|event| event.crashed) {
+        commands.insert_resource(ScreenShake {
+            timer: Timer::from_seconds(SHAKE_SECONDS, false),
+            magnitude: grid_config.shorter_side() * SHAKE_CELLS,
+        });
+    }
+}
+
+// Jitters the camera around the center of the board while a shake lasts, then puts it
+// back. Only the view moves, the board and everything on it stay where they are.
+fn shake_camera(
+    mut commands: Commands,
+    time: Res<Time>,
+    screen_shake: Option<ResMut<ScreenShake>>,
+    mut camera_query: Query<&mut Transform, With<GameCamera>>,
+) {
+    let mut screen_shake = match screen_shake {
+        Some(screen_shake) => screen_shake,
+        None => return,
+    };
+    screen_shake.timer.tick(time.delta());
+    let finished = screen_shake.timer.finished();
+    let reach = screen_shake.magnitude * screen_shake.timer.percent_left();
+    for mut transform in camera_query.iter_mut() {
+        let offset = if finished {
+            Vec2::ZERO
+        } else {
+            Vec2::new(
+                rand::random::<f32>() * 2. - 1.,
+                rand::random::<f32>() * 2. - 1.,
+            ) * reach
+        };
+        transform.translation.x = offset.x;
+        transform.translation.y = offset.y;
+    }
+    if finished {
+        commands.remove_resource::<ScreenShake>();
+    }
+}
+
+fn play_eat_sound(
+    mut food_eaten: EventReader<FoodEaten>,
+    sounds: Res<Sounds>,
+    muted: Res<Muted>,
+    audio: Res<Audio>,
+) {
+    for _ in food_eaten.iter() {
+        if !muted.0 {
+            audio.play(sounds.eat.clone());
+        }
+    }
+}
+
+fn toggle_pause(
+    kb: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    game_state: Res<GameState>,
+    mut paused: ResMut<Paused>,
+) {
+    if *game_state == GameState::Running
+        && kb.any_just_pressed([KeyCode::Escape, key_bindings.pause])
+    {
+        paused.0 = !paused.0;
+    }
+}
+
+// Keeps the theme and mute toggles in the settings, and the settings on disk
+fn save_settings(theme: Res<Theme>, muted: Res<Muted>, mut settings: ResMut<Settings>) {
+    if settings.theme != theme.name || settings.muted != muted.0 {
+        settings.theme = theme.name;
+        settings.muted = muted.0;
+    }
+    // The settings just loaded are already on disk, or the defaults
+    if settings.is_changed() && !settings.is_added() {
+        if let Err(error) = settings.save(SETTINGS_PATH) {
+            warn!("could not save the settings: {}", error);
+        }
+    }
+}
+
+fn toggle_mute(kb: Res<Input<KeyCode>>, mut muted: ResMut<Muted>) {
+    if kb.just_pressed(KeyCode::M) {
+        muted.0 = !muted.0;
+    }
+}
+
+// Draws everything on the board on the cell its GridPos holds. render_interpolate may
+// still slide the snake part of the way there afterwards.
+fn sync_transforms(
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    mut query: Query<(&GridPos, &mut Transform)>,
+) {
+    for (pos, mut transform) in query.iter_mut() {
+        let position = grid_config.grid_to_world(pos.x, pos.y, &board);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+// Notes where each segment came from on the ticks it moves
+fn track_motion(
+    tick: Res<Tick>,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    mut segment_query: Query<(&GridPos, &mut Motion)>,
+) {
+    if tick.allowed {
+        for (pos, mut motion) in segment_query.iter_mut() {
+            motion.from = motion.to;
+            motion.to = grid_config
+                .grid_to_world(pos.x, pos.y, &board)
+                .extend(SNAKE_LAYER);
+        }
+    }
+}
+
+// Draws the segments part of the way between their last two cells, by how much of
+// the current step has passed
+fn render_interpolate(
+    smooth_motion: Res<SmoothMotion>,
+    accumulator: Res<StepAccumulator>,
+    step_interval: Res<StepInterval>,
+    debug_step: Res<DebugStep>,
+    grid_config: Res<GridConfig>,
+    mut segment_query: Query<(&mut Transform, &Motion)>,
+) {
+    if smooth_motion.0 {
+        let fraction = accumulator.fraction(debug_step.scaled(step_interval.seconds)) as f32;
+        for (mut transform, motion) in segment_query.iter_mut() {
+            transform.translation =
+                motion.interpolate(fraction, grid_config.cell_size().max_element());
+        }
+    }
+}
+
+// A new board size from the options gets a new checkerboard and border
+#[allow(clippy::type_complexity)]
+fn resize_board(
+    mut commands: Commands,
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    show_grid: Res<ShowGrid>,
+    theme: Res<Theme>,
+    tile_query: Query<Entity, Or<(With<GridTile>, With<Border>)>>,
+) {
+    // The startup board already has its tiles and border from setup_grid
+    if !board.is_changed() || board.is_added() {
+        return;
+    }
+    for entity in tile_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    spawn_grid_tiles(&mut commands, &board, &grid_config, &show_grid, &theme);
+    spawn_border(&mut commands, &board, &grid_config, &theme);
+}
+
+// Keeps WinSize up to date, fit_camera refits the view to it
+fn fit_view_to_window(
+    mut resize_events: EventReader<WindowResized>,
+    mut win_size: ResMut<WinSize>,
+) {
+    for event in resize_events.iter() {
+        if event.id != WindowId::primary() {
+            continue;
+        }
+        win_size.w = event.width;
+        win_size.h = event.height;
+    }
+}
+
+// Scales the view to the board and the window, again whenever either of them changes.
+// The board is laid out around the world origin, so the camera stays centered on it and
+// only shake_camera moves it off for a moment.
+fn fit_camera(
+    board: Res<Board>,
+    grid_config: Res<GridConfig>,
+    win_size: Res<WinSize>,
+    view_mode: Res<ViewMode>,
+    mut camera_query: Query<&mut OrthographicProjection, With<GameCamera>>,
+) {
+    if !(board.is_changed()
+        || grid_config.is_changed()
+        || win_size.is_changed()
+        || view_mode.is_changed())
+    {
+        return;
+    }
+    let needed = board.win_size(&grid_config, BOARD_MARGIN);
+    for mut projection in camera_query.iter_mut() {
+        projection.scale = view_scale(&needed, &win_size, *view_mode);
+    }
+}
+
+// Projection scale that shows `needed` pixels of the world in the window, letterboxed on
+// whichever side has room to spare. At 1:1 pixels the scale stays put, whatever fits.
+fn view_scale(needed: &WinSize, win_size: &WinSize, view_mode: ViewMode) -> f32 {
+    match view_mode {
+        ViewMode::Fit => (needed.w / win_size.w).max(needed.h / win_size.h),
+        ViewMode::Pixels => 1.,
+    }
+}
+
+fn toggle_grid(
+    kb: Res<Input<KeyCode>>,
+    mut show_grid: ResMut<ShowGrid>,
+    mut tile_query: Query<&mut Visibility, With<GridTile>>,
+) {
+    if kb.just_pressed(KeyCode::G) {
+        show_grid.0 = !show_grid.0;
+        for mut visibility in tile_query.iter_mut() {
+            visibility.is_visible = show_grid.0;
+        }
+    }
+}
+// F11 goes borderless fullscreen on the current monitor and back. The resize that follows
+// refits the board to the new window size in fit_camera.
+fn toggle_fullscreen(kb: Res<Input<KeyCode>>, mut windows: ResMut<Windows>) {
+    if kb.just_pressed(KeyCode::F11) {
+        if let Some(window) = windows.get_primary_mut() {
+            window.set_mode(toggled_mode(window.mode()));
+        }
+    }
+}
+
+fn toggled_mode(mode: WindowMode) -> WindowMode {
+    match mode {
+        WindowMode::Windowed => WindowMode::BorderlessFullscreen,
+        _ => WindowMode::Windowed,
+    }
+}
+
+fn toggle_diagnostics(
+    kb: Res<Input<KeyCode>>,
+    mut show_diagnostics: ResMut<ShowDiagnostics>,
+    mut text_query: Query<&mut Visibility, With<DiagnosticsText>>,
+) {
+    if kb.just_pressed(KeyCode::F3) {
+        show_diagnostics.0 = !show_diagnostics.0;
+        for mut visibility in text_query.iter_mut() {
+            visibility.is_visible = show_diagnostics.0;
+        }
+    }
+}
+
+// The measured frame rate next to the logical step rate, which should hold steady however
+// the frames fall
+fn update_diagnostics_text(
+    show_diagnostics: Res<ShowDiagnostics>,
+    diagnostics: Res<Diagnostics>,
+    step_interval: Res<StepInterval>,
+    mut text_query: Query<&mut Text, With<DiagnosticsText>>,
+) {
+    if !show_diagnostics.0 {
+        return;
+    }
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.average())
+        .unwrap_or(0.);
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!(
+            "FPS: {:.0}  Step: {:.3}s ({:.1}/s)",
+            fps,
+            step_interval.seconds,
+            1. / step_interval.seconds
+        );
+    }
+}
+
+// F6 toggles slow motion, F7 runs a single step while the game is paused
+#[cfg(feature = "debug-controls")]
+fn debug_step_controls(
+    kb: Res<Input<KeyCode>>,
+    paused: Res<Paused>,
+    mut debug_step: ResMut<DebugStep>,
+) {
+    if kb.just_pressed(KeyCode::F6) {
+        debug_step.slow_motion = !debug_step.slow_motion;
+    }
+    if kb.just_pressed(KeyCode::F7) && paused.0 {
+        debug_step.step_once = true;
+    }
+}
+
+// F8 in the menu switches the stress test on or off for the next rounds
+#[cfg(feature = "debug-controls")]
+fn toggle_stress_test(kb: Res<Input<KeyCode>>, mut stress_test: ResMut<StressTest>) {
+    if kb.just_pressed(KeyCode::F8) {
+        stress_test.0 = !stress_test.0;
+        info!("stress test {}", if stress_test.0 { "on" } else { "off" });
+    }
+}
+
+fn toggle_cell_overlay(
+    kb: Res<Input<KeyCode>>,
+    mut show_cell_overlay: ResMut<ShowCellOverlay>,
+    mut text_query: Query<&mut Visibility, With<CellOverlayText>>,
+) {
+    if kb.just_pressed(KeyCode::F1) {
+        show_cell_overlay.0 = !show_cell_overlay.0;
+        for mut visibility in text_query.iter_mut() {
+            visibility.is_visible = show_cell_overlay.0;
+        }
+    }
+}
+
+// The cells everything is on, to check that movement and placement stay on the lattice
+fn update_cell_overlay_text(
+    show_cell_overlay: Res<ShowCellOverlay>,
+    snake_query: Query<(&Player, &Snake)>,
+    pos_query: Query<&GridPos>,
+    food_query: Query<&GridPos, With<Food>>,
+    mut text_query: Query<&mut Text, With<CellOverlayText>>,
+) {
+    if !show_cell_overlay.0 {
+        return;
+    }
+    let snakes: Vec<(Player, Vec<GridPos>)> = snakes_by_player(&snake_query)
+        .into_iter()
+        .map(|(player, segments)| {
+            let cells = segments
+                .iter()
+                .filter_map(|&segment| pos_query.get(segment).ok().copied())
+                .collect();
+            (player, cells)
+        })
+        .collect();
+    let foods: Vec<GridPos> = food_query.iter().copied().collect();
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = cell_overlay_lines(&snakes, &foods);
+    }
+}
+
+// A line for each snake with its head and tail end cells and its length, then the foods
+fn cell_overlay_lines(snakes: &[(Player, Vec<GridPos>)], foods: &[GridPos]) -> String {
+    let mut lines = String::new();
+    for (player, cells) in snakes {
+        if let (Some(head), Some(tail)) = (cells.first(), cells.last()) {
+            lines += &format!(
+                "P{} head: {:?}  tail: {:?}  length: {}\n",
+                player.0 + 1,
+                head.cell(),
+                tail.cell(),
+                cells.len()
+            );
+        }
+    }
+    let food_cells: Vec<String> = foods
+        .iter()
+        .map(|food| format!("{:?}", food.cell()))
+        .collect();
+    lines += &format!("Food: {}", food_cells.join(" "));
+    lines
+}
+
+// One line per pause menu entry, the selected one marked
+fn pause_lines(selected: usize) -> impl Iterator<Item = String> {
+    PauseOption::ALL
+        .iter()
+        .enumerate()
+        .map(move |(index, option)| {
+            let marker = if index == selected { "> " } else { "  " };
+            format!("{}{}\n", marker, option.label())
+        })
+}
+
+// While paused, Up/Down picks an entry of the pause menu and Enter takes it. Enter is used
+// up here, so a menu reached through it doesn't start a round on the same press.
+fn pause_menu(
+    mut kb: ResMut<Input<KeyCode>>,
+    mut paused: ResMut<Paused>,
+    mut selected: Local<usize>,
+    mut app_state: ResMut<State<AppState>>,
+    mut game_reset: EventWriter<GameReset>,
+    mut app_exit: EventWriter<AppExit>,
+    mut text_query: Query<&mut Text, With<PauseText>>,
+) {
+    // Every pause starts on Resume
+    let opened = paused.is_changed();
+    if opened {
+        *selected = 0;
+    }
+    if !paused.0 {
+        return;
+    }
+
+    let options = PauseOption::ALL.len();
+    let previous = *selected;
+    if kb.just_pressed(KeyCode::Up) {
+        *selected = (*selected + options - 1) % options;
+    } else if kb.just_pressed(KeyCode::Down) {
+        *selected = (*selected + 1) % options;
+    } else if kb.clear_just_pressed(KeyCode::Return) {
+        match PauseOption::ALL[*selected] {
+            PauseOption::Resume => paused.0 = false,
+            PauseOption::Restart => {
+                app_state.restart().unwrap();
+                game_reset.send(GameReset);
+            }
+            PauseOption::QuitToMenu => app_state.set(AppState::Menu).unwrap(),
+            // Leaving the round first, cleanup_game runs as for any other way out of it
+            PauseOption::Quit => {
+                app_state.set(AppState::Menu).unwrap();
+                app_exit.send(AppExit);
+            }
+        }
+    }
+
+    if opened || *selected != previous {
+        for mut text in text_query.iter_mut() {
+            for (section, line) in text.sections[1..].iter_mut().zip(pause_lines(*selected)) {
+                section.value = line;
+            }
+        }
+    }
+}
+
+fn update_pause_text(paused: Res<Paused>, mut text_query: Query<&mut Visibility, With<PauseText>>) {
+    if paused.is_changed() {
+        for mut visibility in text_query.iter_mut() {
+            visibility.is_visible = paused.0;
+        }
+    }
+}
+
+fn update_victory_text(
+    game_state: Res<GameState>,
+    mut text_query: Query<&mut Visibility, With<VictoryText>>,
+) {
+    if game_state.is_changed() {
+        for mut visibility in text_query.iter_mut() {
+            visibility.is_visible = *game_state == GameState::Victory;
+        }
+    }
+}
+
+// Each second of the countdown and the "Go!" after it as a toast, lasting until the next
+// one shows up. A new countdown starts over from its first label.
+fn toast_countdown(
+    clock: Res<GameClock>,
+    countdown: Res<Countdown>,
+    mut shown: Local<Option<String>>,
+    mut toasts: EventWriter<Toast>,
+) {
+    if countdown.is_changed() {
+        *shown = None;
+    }
+    let label = countdown.label(clock.seconds);
+    if label == *shown {
+        return;
+    }
+    if let Some(text) = &label {
+        let left = countdown.ends_at - clock.seconds;
+        let seconds = if left > 0. {
+            left - left.ceil() + 1.
+        } else {
+            left + GO_SECONDS
+        };
+        toasts.send(Toast::center(text, seconds as f32));
+    }
+    *shown = label;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_new_tail(
+    mut commands: Commands,
+    grid_config: Res<GridConfig>,
+    board: Res<Board>,
+    body_query: Query<&GridPos, Without<Food>>,
+    mut snake_query: Query<(&Player, &mut Snake, &mut LateSpawn), With<Head>>,
+    mut occupied: ResMut<OccupiedCells>,
+    tick: Res<Tick>,
+    theme: Res<Theme>,
+    max_length: Res<MaxLength>,
+) {
+    if !tick.allowed {
+        return;
+    }
+    for (player, mut snake, mut tail_spawner) in snake_query.iter_mut() {
+        let segments = &mut snake.segments;
+        // A snake at the cap forgets any growth it still had coming
+        if max_length.0.is_some_and(|max| segments.len() >= max) {
+            tail_spawner.pending = 0;
+            continue;
+        }
+        let last_entity = segments.last().unwrap();
+        if let Ok(last_pos) = body_query.get(*last_entity) {
+            if tail_spawner.pending > 0 && *last_pos != tail_spawner.cell {
+                if !tail_spawner.wait {
+                    let length = segments.len() + 1;
+                    let color = theme.player_segment_color(*player, length - 1, length);
+                    let tail_entity = spawn_tail(
+                        &mut commands,
+                        &grid_config,
+                        &board,
+                        *player,
+                        *last_pos,
+                        color,
+                        true,
+                    );
+
+                    segments.push(tail_entity);
+                    occupied.add(last_pos.cell());
+                    tail_spawner.pending -= 1;
+                    // Any further segment waits for this new one to move off in turn
+                    tail_spawner.cell = *last_pos;
+                    tail_spawner.wait = true;
+                }
+                tail_spawner.wait = false;
+            }
+        }
+    }
+}
+
+// A segment grown in play starts at scale 0 and grow_in brings it up to size
+fn spawn_tail(
+    commands: &mut Commands,
+    grid_config: &GridConfig,
+    board: &Board,
+    player: Player,
+    pos: GridPos,
+    color: Color,
+    grows_in: bool,
+) -> Entity {
+    let translation = grid_config
+        .grid_to_world(pos.x, pos.y, board)
+        .extend(SNAKE_LAYER);
+    let mut tail = commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::new(grid_config.tail_size(), grid_config.tail_size())),
+            ..Default::default()
+        },
+        transform: Transform {
+            translation,
+            scale: if grows_in { Vec3::ZERO } else { Vec3::ONE },
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    if grows_in {
+        tail.insert(GrowIn::new(GROW_IN_SECONDS));
+    }
+    tail.insert(Tail)
+        .insert(player)
+        .insert(pos)
+        .insert(Motion::new(translation))
+        .with_children(|tail| {
+            // Pointing right until shape_snake turns the segment towards its neighbor
+            tail.spawn_bundle(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(grid_config.cell_w, grid_config.tail_size())),
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(grid_config.cell_w / 2., 0., 0.),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(Joint);
+        })
+        .id()
+}
+
+// Returns the index of the body segment the head (segments[0]) overlaps.
+// Every segment but the head itself is checked, no fixed skip is needed: the head
+// moves exactly one cell per tick and can't reverse, so it never lands on the neck
+// (index 1) in practice, and skipping further segments would hide real collisions.
+fn self_collision_index(segments: &[GridPos]) -> Option<usize> {
+    let head = segments.first()?;
+    segments
+        .iter()
+        .skip(1)
+        .position(|segment| segment == head)
+        .map(|index| index + 1)
+}
+
+// Only the walls around the board end a round, in wrap mode the head never leaves it
+fn left_the_board(head: GridPos, board: &Board, boundary_mode: BoundaryMode) -> bool {
+    match boundary_mode {
+        BoundaryMode::Wall => !board.contains(head.cell()),
+        BoundaryMode::Wrap => false,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn collision_check(
+    mut commands: Commands,
+    board: Res<Board>,
+    boundary_mode: Res<BoundaryMode>,
+    self_collision_mode: Res<SelfCollisionMode>,
+    tick: Res<Tick>,
+    collisions_enabled: Res<CollisionsEnabled>,
+    mut game_state: ResMut<GameState>,
+    mut game_over: EventWriter<GameOver>,
+    mut occupied: ResMut<OccupiedCells>,
+    mut snake_query: Query<(&Player, &mut Snake)>,
+    body_query: Query<&GridPos, Without<Food>>,
+    rotten_query: Query<(Entity, &GridPos), With<Rotten>>,
+) {
+    if !tick.allowed || !collisions_enabled.0 {
+        return;
+    }
+    let mut finished: bool = false;
+    let mut crashed: bool = false;
+    for (player, mut snake) in snake_query.iter_mut() {
+        let segments: Vec<GridPos> = snake
+            .segments
+            .iter()
+            .filter_map(|entity| body_query.get(*entity).ok())
+            .copied()
+            .collect();
+        let head = segments[0];
+        if left_the_board(head, &board, *boundary_mode) {
+            info!("player {} ran off the board", player.0);
+            crashed = true;
+        }
+        // The head shares its cell with a wall, its own body or any part of another snake
+        let sharing = occupied.count(head.cell());
+        if sharing > 1 {
+            match self_collision_index(&segments) {
+                // Nothing but its own segment is in the way, that one and the rest drop off
+                Some(index)
+                    if *self_collision_mode == SelfCollisionMode::Truncate && sharing == 2 =>
+                {
+                    info!("player {} bit off its tail at segment {}", player.0, index);
+                    drop_tail(&mut commands, &mut occupied, &mut snake, &segments, index);
+                }
+                Some(index) => {
+                    info!("player {} ran into its own segment {}", player.0, index);
+                    crashed = true;
+                }
+                None => {
+                    info!("player {} crashed", player.0);
+                    crashed = true;
+                }
+            }
+        }
+        // Rotten food ends the round too, unless in truncate mode there's a tail to lose
+        if let Some((rotten, _)) = rotten_query.iter().find(|(_, pos)| **pos == head) {
+            commands.entity(rotten).despawn();
+            let keep = snake
+                .segments
+                .len()
+                .saturating_sub(ROTTEN_FOOD_SHRINK)
+                .max(1);
+            if *self_collision_mode == SelfCollisionMode::Truncate && keep < snake.segments.len() {
+                info!("player {} ate rotten food and shrank to {}", player.0, keep);
+                drop_tail(&mut commands, &mut occupied, &mut snake, &segments, keep);
+            } else {
+                info!("player {} ate rotten food", player.0);
+                finished = true;
+            }
+        }
+    }
+
+    if finished || crashed {
+        *game_state = GameState::GameOver;
+        game_over.send(GameOver {
+            won: false,
+            crashed,
+        });
+    }
+}
+
+// Despawns the snake's segments from `from` on. `segments` are the cells of all of them,
+// head first, as they were before any were dropped.
+fn drop_tail(
+    commands: &mut Commands,
+    occupied: &mut OccupiedCells,
+    snake: &mut Snake,
+    segments: &[GridPos],
+    from: usize,
+) {
+    for (entity, pos) in snake
+        .segments
+        .split_off(from)
+        .into_iter()
+        .zip(&segments[from..])
+    {
+        occupied.remove(pos.cell());
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::event::Events;
+
+    fn cell(x: i32, y: i32) -> GridPos {
+        GridPos::new(x, y)
+    }
+
+    #[test]
+    fn self_collision_detects_loop_onto_mid_body() {
+        // Head at (1, 0) coming back around onto the fourth segment
+        let segments = [
+            cell(1, 0),
+            cell(1, 1),
+            cell(0, 1),
+            cell(0, 0),
+            cell(1, 0),
+            cell(2, 0),
+        ];
+        assert_eq!(self_collision_index(&segments), Some(4));
+
+        let straight = [cell(3, 0), cell(2, 0), cell(1, 0), cell(0, 0)];
+        assert_eq!(self_collision_index(&straight), None);
+    }
+
+    #[test]
+    fn self_collision_on_neck_is_reported() {
+        // Unreachable in one step, but if it happens it must not be silently skipped
+        let segments = [cell(0, 0), cell(0, 0), cell(1, 0)];
+        assert_eq!(self_collision_index(&segments), Some(1));
+
+        assert_eq!(self_collision_index(&[cell(0, 0)]), None);
+        assert_eq!(self_collision_index(&[]), None);
+    }
+
+    #[test]
+    fn leaving_the_board_only_counts_with_walls() {
+        let board = Board { cols: 2, rows: 2 };
+        assert!(left_the_board(cell(2, 0), &board, BoundaryMode::Wall));
+        assert!(!left_the_board(cell(1, 0), &board, BoundaryMode::Wall));
+        assert!(!left_the_board(cell(2, 0), &board, BoundaryMode::Wrap));
+    }
+
+    #[test]
+    fn food_cells_lie_wholly_on_the_board_for_awkward_windows() {
+        let grid_config = GridConfig::new();
+        for (width, height) in [
+            (800., 600.),
+            (810., 610.),
+            (777., 333.),
+            (425.5, 180.),
+            (10., 10.),
+        ] {
+            let win_size = WinSize {
+                w: width,
+                h: height,
+            };
+            let board = Board::fit(&win_size, &grid_config, BOARD_MARGIN);
+            let shown = board.win_size(&grid_config, BOARD_MARGIN);
+            let half_cell = grid_config.cell_size() / 2.;
+            let free = free_tiles(board.cols as i32, board.rows as i32, &HashSet::default());
+            assert_eq!(free.len(), (board.cols * board.rows) as usize);
+            for (x, y) in free {
+                let center = grid_config.grid_to_world(x, y, &board);
+                assert!(center.x.abs() + half_cell.x <= shown.w / 2. + 0.001);
+                assert!(center.y.abs() + half_cell.y <= shown.h / 2. + 0.001);
+                assert_eq!(grid_config.world_to_grid(center.extend(0.), &board), (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn border_lines_up_with_the_board_edges() {
+        let grid_config = GridConfig::new();
+        let board = Board { cols: 16, rows: 12 };
+        let half_cell = grid_config.cell_size() / 2.;
+        let [top, bottom, left, right] = border_strips(&board, &grid_config, BOARD_MARGIN);
+        let inner_edge = |(center, size): (Vec2, Vec2)| center - size / 2.;
+        let outer_edge = |(center, size): (Vec2, Vec2)| center + size / 2.;
+
+        // The last cells on the board touch the strips, the first ones off it are under them
+        let (low, high) = (
+            grid_config.grid_to_world(0, 0, &board) - half_cell,
+            grid_config.grid_to_world(15, 11, &board) + half_cell,
+        );
+        assert!(board.contains((15, 11)) && !board.contains((16, 12)));
+        assert_eq!(inner_edge(right).x, high.x);
+        assert_eq!(inner_edge(top).y, high.y);
+        assert_eq!(outer_edge(left).x, low.x);
+        assert_eq!(outer_edge(bottom).y, low.y);
+
+        // Together they fill the margin of the window the board is shown in
+        let shown = board.win_size(&grid_config, BOARD_MARGIN);
+        assert_eq!(outer_edge(right).x, shown.w / 2.);
+        assert_eq!(outer_edge(top), Vec2::new(shown.w, shown.h) / 2.);
+        assert_eq!(inner_edge(bottom), -Vec2::new(shown.w, shown.h) / 2.);
+        assert_eq!(left.1.y, high.y - low.y);
+    }
+
+    #[test]
+    fn head_visits_every_food_position_on_odd_windows() {
+        let grid_config = GridConfig::new();
+        for (width, height) in [(810., 610.), (777., 333.), (1234., 599.), (425.5, 180.)] {
+            let board = Board::fit(
+                &WinSize {
+                    w: width,
+                    h: height,
+                },
+                &grid_config,
+                BOARD_MARGIN,
+            );
+            let (spawn_x, spawn_y) = grid_config.spawn_cell(&board);
+
+            // Every position a head reaches going right, then up, with wrapping around
+            let mut visited = Vec::new();
+            let mut head = cell(spawn_x, spawn_y);
+            for direction in [Direction::RIGHT, Direction::UP] {
+                for _ in 0..board.cols.max(board.rows) {
+                    head = next_head_cell(head, direction, &board, BoundaryMode::Wrap);
+                    visited.push(head);
+                }
+            }
+
+            for x in 0..board.cols as i32 {
+                for y in 0..board.rows as i32 {
+                    assert!(
+                        visited.iter().any(|head| head.x == x)
+                            && visited.iter().any(|head| head.y == y),
+                        "{}x{} window, cell ({}, {})",
+                        width,
+                        height,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbor_direction_only_joins_touching_cells() {
+        let from = GridPos::new(4, 4);
+        assert_eq!(
+            neighbor_direction(from, GridPos::new(5, 4)),
+            Some(Direction::RIGHT)
+        );
+        assert_eq!(
+            neighbor_direction(from, GridPos::new(4, 3)),
+            Some(Direction::DOWN)
+        );
+        // The same cell, a diagonal and a jump across a wrapped edge have nothing to join
+        assert_eq!(neighbor_direction(from, from), None);
+        assert_eq!(neighbor_direction(from, GridPos::new(5, 5)), None);
+        assert_eq!(
+            neighbor_direction(GridPos::new(0, 4), GridPos::new(15, 4)),
+            None
+        );
+    }
+
+    #[test]
+    fn fullscreen_toggles_back_to_a_window() {
+        assert_eq!(
+            toggled_mode(WindowMode::Windowed),
+            WindowMode::BorderlessFullscreen
+        );
+        assert_eq!(
+            toggled_mode(WindowMode::BorderlessFullscreen),
+            WindowMode::Windowed
+        );
+        assert_eq!(toggled_mode(WindowMode::Fullscreen), WindowMode::Windowed);
+    }
+
+    fn splash_app(seconds: f32) -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .init_resource::<Input<KeyCode>>()
+            .add_state(AppState::Splash)
+            .insert_resource(SplashTimer(Timer::from_seconds(seconds, false)))
+            .add_system_set(SystemSet::on_update(AppState::Splash).with_system(end_splash))
+            .add_system_set(SystemSet::on_exit(AppState::Splash).with_system(cleanup_splash));
+        app.world.spawn().insert(SplashText);
+        app
+    }
+
+    #[test]
+    fn splash_gives_way_to_the_menu() {
+        let mut app = splash_app(0.);
+        app.update();
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<AppState>>().current(),
+            AppState::Menu
+        );
+        let mut splash_query = app.world.query_filtered::<Entity, With<SplashText>>();
+        assert_eq!(splash_query.iter(&app.world).count(), 0);
+        assert!(app.world.get_resource::<SplashTimer>().is_none());
+
+        // Enter skips the rest of it, and isn't left over for the menu
+        let mut app = splash_app(SPLASH_SECONDS);
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<AppState>>().current(),
+            AppState::Splash
+        );
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Return);
+        app.update();
+        assert_eq!(
+            *app.world.resource::<State<AppState>>().current(),
+            AppState::Menu
+        );
+        assert!(!app
+            .world
+            .resource::<Input<KeyCode>>()
+            .just_pressed(KeyCode::Return));
+    }
+
+    #[test]
+    fn headless_eating_the_objective_wins_the_round() {
+        let mut app = restarted_with(headless_app(), Objective::new(1));
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+
+        // Up from (9, 6) onto the food at (9, 7)
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        assert_eq!(app.world.resource::<Objective>().eaten, 1);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Victory);
+        let events = app.world.resource::<Events<GameOver>>();
+        let ends: Vec<GameOver> = events.get_reader().iter(events).copied().collect();
+        assert_eq!(
+            ends,
+            vec![GameOver {
+                won: true,
+                crashed: false
+            }]
+        );
+
+        // Playing again counts from nothing towards the same target
+        let app = restarted_with(app, GameState::Running);
+        assert_eq!(app.world.resource::<Objective>().eaten, 0);
+        assert_eq!(app.world.resource::<Objective>().target, 1);
+    }
+
+    #[test]
+    fn headless_length_scoring_counts_the_body() {
+        let mut app = headless_app();
+        app.insert_resource(StartLength(3));
+        let mut app = restarted_with(app, ScoringMode::Length);
+        assert_eq!(app.world.resource::<Score>().value, 2);
+
+        // Up onto the food at (9, 7) from (9, 6), the points of the food don't count
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        assert_eq!(app.world.resource::<Score>().value, 2);
+        let mut query = app.world.query_filtered::<&mut GridPos, With<Food>>();
+        for mut pos in query.iter_mut(&mut app.world) {
+            *pos = cell(0, 0);
+        }
+        for _ in 0..3 {
+            step(&mut app);
+        }
+        assert_eq!(snake_segments(&mut app, Player(0)).len(), 4);
+        app.update();
+        assert_eq!(app.world.resource::<Score>().value, 3);
+    }
+
+    #[test]
+    fn serpentine_winds_down_the_board_without_crossing_itself() {
+        let board = Board { cols: 16, rows: 12 };
+        let cells = serpentine_cells(&board, STRESS_LENGTH);
+        // Cut down to the board less its last column and bottom two rows
+        assert_eq!(cells.len(), 15 * 10);
+        assert_eq!(&cells[..2], &[(14, 11), (13, 11)]);
+        assert_eq!(cells.iter().collect::<HashSet<_>>().len(), cells.len());
+        assert!(cells.windows(2).all(|pair| {
+            let ((x, y), (next_x, next_y)) = (pair[0], pair[1]);
+            (x - next_x).abs() + (y - next_y).abs() == 1
+        }));
+        assert!(cells
+            .iter()
+            .all(|&(x, y)| board.contains((x, y)) && x < 15 && y >= 2));
+        assert_eq!(
+            serpentine_cells(&board, 3),
+            vec![(14, 11), (13, 11), (12, 11)]
+        );
+    }
+
+    #[test]
+    fn headless_stress_test_starts_a_long_snake_that_can_move() {
+        let mut app = restarted_with(headless_app(), StressTest(true));
+        assert_eq!(snake_segments(&mut app, Player(0)).len(), 150);
+        let snake: HashSet<(i32, i32)> = snake_cells(&mut app).into_iter().collect();
+        let mut food_query = app.world.query_filtered::<&GridPos, With<Food>>();
+        assert!(food_query
+            .iter(&app.world)
+            .all(|food| !snake.contains(&food.cell())));
+
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        steer(&mut app, Direction::DOWN);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app)[0], (15, 10));
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn headless_length_stops_at_the_cap_but_the_score_goes_on() {
+        let mut app = restarted_with(headless_app(), MaxLength(Some(2)));
+        steer(&mut app, Direction::RIGHT);
+        let mut scores = vec![app.world.resource::<Score>().value];
+        // A food right in front of the head for each of the next steps
+        for x in 9..13 {
+            let mut query = app.world.query_filtered::<&mut GridPos, With<Food>>();
+            if let Some(mut pos) = query.iter_mut(&mut app.world).next() {
+                *pos = cell(x, 6);
+            }
+            step(&mut app);
+            scores.push(app.world.resource::<Score>().value);
+        }
+        // Four foods would make five segments, give the late tail time to show up
+        steer(&mut app, Direction::DOWN);
+        for _ in 0..4 {
+            step(&mut app);
+        }
+        assert_eq!(snake_segments(&mut app, Player(0)).len(), 2);
+        step(&mut app);
+        assert_eq!(snake_segments(&mut app, Player(0)).len(), 2);
+        assert!(
+            scores.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            scores
+        );
+    }
+
+    #[test]
+    fn quick_foods_score_with_the_combo_multiplier() {
+        let mut app = App::new();
+        app.insert_resource(Score { value: 0 })
+            .insert_resource(Combo::new())
+            .insert_resource(ScoringMode::Points)
+            .add_event::<FoodEaten>()
+            .add_system(award_food_score);
+        let food = FoodEaten {
+            player: Player(0),
+            cell: (0, 0),
+            points: 10,
+            golden: false,
+        };
+        app.world.resource_mut::<Events<FoodEaten>>().send(food);
+        app.update();
+        app.world.resource_mut::<Events<FoodEaten>>().send(food);
+        app.update();
+        assert_eq!(app.world.resource::<Score>().value, 10 + 2 * 10);
+        assert_eq!(app.world.resource::<Combo>().multiplier, 2);
+    }
+
+    #[test]
+    fn free_tiles_skip_occupied() {
+        let occupied: HashSet<(i32, i32)> = [(0, 0), (1, 0), (0, 1)].into_iter().collect();
+        assert_eq!(free_tiles(2, 2, &occupied), vec![(1, 1)]);
+
+        let full: HashSet<(i32, i32)> = [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().collect();
+        assert!(free_tiles(2, 2, &full).is_empty());
+    }
+
+    #[test]
+    fn body_trails_a_head_moving_several_cells_in_one_step() {
+        let segments = [cell(5, 6), cell(4, 6), cell(3, 6), cell(2, 6)];
+        let followed = follow_head(&[cell(6, 6), cell(7, 6)], &segments);
+        assert_eq!(
+            followed,
+            vec![cell(7, 6), cell(6, 6), cell(5, 6), cell(4, 6)]
+        );
+        // No gap anywhere along the body
+        assert!(followed.windows(2).all(|pair| {
+            let ((x, y), (next_x, next_y)) = (pair[0].cell(), pair[1].cell());
+            (x - next_x).abs() + (y - next_y).abs() == 1
+        }));
+
+        // A path longer than the snake leaves it all on the path
+        let followed = follow_head(&[cell(6, 6), cell(7, 6), cell(8, 6)], &segments[..2]);
+        assert_eq!(followed, vec![cell(8, 6), cell(7, 6)]);
+        // A single cell is the regular step
+        let followed = follow_head(&[cell(6, 6)], &segments);
+        assert_eq!(
+            followed,
+            vec![cell(6, 6), cell(5, 6), cell(4, 6), cell(3, 6)]
+        );
+    }
+
+    #[test]
+    fn countdown_toasts_each_second_once() {
+        let mut app = App::new();
+        app.insert_resource(GameClock { seconds: 0. })
+            .insert_resource(Countdown::new(0., 3.))
+            .add_event::<Toast>()
+            .add_system(toast_countdown);
+        let toasts = |app: &mut App| {
+            let mut events = app.world.resource_mut::<Events<Toast>>();
+            events.drain().collect::<Vec<Toast>>()
+        };
+        app.update();
+        assert_eq!(toasts(&mut app), vec![Toast::center("3", 1.)]);
+        app.world.resource_mut::<GameClock>().seconds = 0.5;
+        app.update();
+        assert!(toasts(&mut app).is_empty());
+
+        app.world.resource_mut::<GameClock>().seconds = 1.5;
+        app.update();
+        assert_eq!(toasts(&mut app), vec![Toast::center("2", 0.5)]);
+    }
+
+    #[test]
+    fn toasts_fade_out_at_the_end_then_go() {
+        let mut app = App::new();
+        app.insert_resource(Time::default()).add_system(fade_toasts);
+        let mut toast = ToastText::new(1.);
+        toast.timer.tick(std::time::Duration::from_secs_f32(0.5));
+        let entity = app
+            .world
+            .spawn()
+            .insert(toast)
+            .insert(Text::with_section(
+                "Hi",
+                TextStyle::default(),
+                Default::default(),
+            ))
+            .id();
+        app.update();
+        let text = app.world.get::<Text>(entity).unwrap();
+        assert_eq!(text.sections[0].style.color.a(), 1.);
+
+        // Half way through the fade
+        app.world.get_mut::<ToastText>(entity).unwrap().timer.tick(
+            std::time::Duration::from_secs_f32(0.5 - TOAST_FADE_SECONDS / 2.),
+        );
+        app.update();
+        let alpha = app.world.get::<Text>(entity).unwrap().sections[0]
+            .style
+            .color
+            .a();
+        assert!((alpha - 0.5).abs() < 1e-3, "{}", alpha);
+
+        app.world
+            .get_mut::<ToastText>(entity)
+            .unwrap()
+            .timer
+            .tick(std::time::Duration::from_secs_f32(1.));
+        app.update();
+        assert!(app.world.get_entity(entity).is_none());
+    }
+
+    #[test]
+    fn only_crashes_shake_the_camera() {
+        let mut app = App::new();
+        app.insert_resource(GridConfig::new())
+            .add_event::<GameOver>()
+            .add_system(start_shake);
+        app.world.resource_mut::<Events<GameOver>>().send(GameOver {
+            won: false,
+            crashed: false,
+        });
+        app.update();
+        assert!(app.world.get_resource::<ScreenShake>().is_none());
+
+        app.world.resource_mut::<Events<GameOver>>().send(GameOver {
+            won: false,
+            crashed: true,
+        });
+        app.update();
+        let shake = app.world.resource::<ScreenShake>();
+        assert_eq!(
+            shake.magnitude,
+            GridConfig::new().shorter_side() * SHAKE_CELLS
+        );
+    }
+
+    #[test]
+    fn camera_settles_back_to_center_after_a_shake() {
+        let mut app = App::new();
+        app.insert_resource(Time::default())
+            .add_system(shake_camera);
+        let mut timer = Timer::from_seconds(SHAKE_SECONDS, false);
+        timer.tick(std::time::Duration::from_secs_f32(SHAKE_SECONDS));
+        app.insert_resource(ScreenShake {
+            timer,
+            magnitude: 10.,
+        });
+        let camera = app
+            .world
+            .spawn()
+            .insert(Transform::from_xyz(4., -3., 999.9))
+            .insert(GameCamera)
+            .id();
+        app.update();
+        let transform = app.world.get::<Transform>(camera).unwrap();
+        assert_eq!(transform.translation, Vec3::new(0., 0., 999.9));
+        assert!(app.world.get_resource::<ScreenShake>().is_none());
+    }
+
+    #[test]
+    fn filling_the_board_is_a_victory() {
+        // On a 2x2 board the head at tile (1, 1) eats the last free tile
+        let mut app = App::new();
+        app.insert_resource(Board { cols: 2, rows: 2 })
+            .insert_resource(GridConfig::new())
+            .insert_resource(GameState::Running)
+            .insert_resource(Score { value: 0 })
+            .insert_resource(StepInterval::new())
+            .insert_resource(GoldenFoodChance(0.))
+            .insert_resource(RottenFoodChance(0.))
+            .insert_resource(LevelLayout::default())
+            .insert_resource(Theme::default())
+            .insert_resource(GameRng::new(0))
+            .add_event::<FoodEaten>()
+            .add_event::<GameOver>()
+            .add_system(eat_food);
+
+        let mut occupied = OccupiedCells::default();
+        let mut segments = Vec::new();
+        for (x, y) in [(1, 1), (1, 0), (0, 0), (0, 1)] {
+            occupied.add((x, y));
+            let entity = app.world.spawn().insert(cell(x, y)).id();
+            segments.push(entity);
+        }
+        app.world
+            .entity_mut(segments[0])
+            .insert(Head)
+            .insert(Player(0))
+            .insert(LateSpawn::new())
+            .insert(Snake { segments });
+        app.insert_resource(occupied);
+        app.world
+            .spawn()
+            .insert(cell(1, 1))
+            .insert(Sprite::default())
+            .insert(ValueTimer::new())
+            .insert(Food);
+
+        app.update();
+
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Victory);
+    }
+
+    // The simulation on an 800x600 board (16x12 tiles), already past the menu, with the
+    // plugin's defaults. Tests change any of them with restarted_with.
+    fn headless_app() -> App {
+        let mut app = App::new();
+        app.insert_resource(Board { cols: 16, rows: 12 })
+            .add_plugin(SimulationPlugin)
+            // No countdown, the snakes wait for the first turn
+            .insert_resource(CountdownLength(0.))
+            // Keep the board predictable, golden and rotten food are placed by hand where needed
+            .insert_resource(GoldenFoodChance(0.))
+            .insert_resource(RottenFoodChance(0.));
+        app.world
+            .resource_mut::<State<AppState>>()
+            .set(AppState::InGame)
+            .unwrap();
+        app.update();
+        app
+    }
+
+    // Starts the round over with `resource` in place of the one the app had
+    fn restarted_with<R: Send + Sync + 'static>(mut app: App, resource: R) -> App {
+        app.insert_resource(resource);
+        app.world
+            .resource_mut::<State<AppState>>()
+            .restart()
+            .unwrap();
+        app.update();
+        app
+    }
+
+    fn step(app: &mut App) {
+        let interval = app.world.resource::<StepInterval>().seconds;
+        app.world.resource_mut::<GameClock>().seconds += interval + 1e-6;
+        app.update();
+    }
+
+    fn steer(app: &mut App, direction: Direction) {
+        steer_player(app, Player(0), direction);
+    }
+
+    fn steer_player(app: &mut App, player: Player, direction: Direction) {
+        let mut query = app
+            .world
+            .query_filtered::<(&Player, &Velocity, &mut NextDirection), With<Head>>();
+        for (head_player, velocity, mut next_direction) in query.iter_mut(&mut app.world) {
+            // Queued against the way the head goes now, as the input systems do
+            if *head_player == player {
+                next_direction.push(direction, velocity.direction);
+            }
+        }
+    }
+
+    fn snake_segments(app: &mut App, player: Player) -> Vec<Entity> {
+        let mut query = app.world.query::<(&Player, &Snake)>();
+        query
+            .iter(&app.world)
+            .find(|(snake_player, _)| **snake_player == player)
+            .map(|(_, snake)| snake.segments.clone())
+            .unwrap_or_default()
+    }
+
+    fn snake_cells(app: &mut App) -> Vec<(i32, i32)> {
+        player_cells(app, Player(0))
+    }
+
+    fn player_cells(app: &mut App, player: Player) -> Vec<(i32, i32)> {
+        let segments = snake_segments(app, player);
+        segments
+            .iter()
+            .map(|&entity| app.world.get::<GridPos>(entity).unwrap().cell())
+            .collect()
+    }
+
+    #[test]
+    fn headless_snake_moves_one_cell_per_step() {
+        let mut app = headless_app();
+        assert_eq!(snake_cells(&mut app), vec![(8, 6)]);
+
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+
+        // Without a full step interval passing the head stays put
+        app.update();
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+    }
+
+    #[test]
+    fn headless_stall_runs_every_step_due_in_one_frame() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::RIGHT);
+        let interval = app.world.resource::<StepInterval>().seconds;
+        app.world.resource_mut::<GameClock>().seconds += 3. * interval + 1e-6;
+        app.update();
+        assert_eq!(snake_cells(&mut app), vec![(11, 6)]);
+        assert_eq!(app.world.resource::<Tick>().due, 0);
+
+        // Pausing doesn't bank steps for later
+        app.world.resource_mut::<Paused>().0 = true;
+        app.world.resource_mut::<GameClock>().seconds += 3. * interval;
+        app.update();
+        app.world.resource_mut::<Paused>().0 = false;
+        app.update();
+        assert_eq!(snake_cells(&mut app), vec![(11, 6)]);
+    }
+
+    fn occupied_cells(app: &App) -> Vec<(i32, i32)> {
+        let mut cells: Vec<(i32, i32)> = app.world.resource::<OccupiedCells>().cells().collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn headless_occupied_cells_follow_the_snake_as_it_grows() {
+        let mut app = restarted_with(headless_app(), StartLength(3));
+        // Up onto (8, 7), then right onto the food at (9, 7) and on while the tail grows
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        steer(&mut app, Direction::RIGHT);
+        for _ in 0..5 {
+            step(&mut app);
+            let mut cells = snake_cells(&mut app);
+            cells.sort();
+            assert_eq!(occupied_cells(&app), cells);
+        }
+        assert_eq!(snake_cells(&mut app).len(), 4);
+
+        // A new round starts from the fresh snake alone
+        let app = restarted_with(app, StartLength(2));
+        assert_eq!(occupied_cells(&app), vec![(7, 6), (8, 6)]);
+    }
+
+    #[test]
+    fn headless_unpause_picks_up_the_step_where_it_left_off() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+
+        // Most of the next step has passed when the game is paused for a long while
+        let interval = app.world.resource::<StepInterval>().seconds;
+        app.world.resource_mut::<GameClock>().seconds += 0.6 * interval;
+        app.update();
+        app.world.resource_mut::<Paused>().0 = true;
+        app.world.resource_mut::<GameClock>().seconds += 50. * interval;
+        app.update();
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+
+        // Unpaused, the rest of that step is all it takes, and only the one step runs
+        app.world.resource_mut::<Paused>().0 = false;
+        app.update();
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+        app.world.resource_mut::<GameClock>().seconds += 0.5 * interval;
+        app.update();
+        assert_eq!(snake_cells(&mut app), vec![(10, 6)]);
+        assert_eq!(app.world.resource::<Tick>().due, 0);
+    }
+
+    fn tap(app: &mut App, key: KeyCode) {
+        app.world.resource_mut::<Input<KeyCode>>().press(key);
+        app.update();
+        let mut keyboard = app.world.resource_mut::<Input<KeyCode>>();
+        keyboard.release(key);
+        keyboard.clear();
+    }
+
+    fn pause_menu_app() -> App {
+        let mut app = headless_app();
+        app.add_system_set(SystemSet::on_update(AppState::InGame).with_system(pause_menu));
+        app.world.resource_mut::<Paused>().0 = true;
+        app.update();
+        app
+    }
+
+    #[test]
+    fn headless_pause_menu_resumes_and_holds_the_arrow_keys() {
+        let mut app = pause_menu_app();
+        // Moving through the menu doesn't steer the snake
+        tap(&mut app, KeyCode::Down);
+        tap(&mut app, KeyCode::Up);
+        tap(&mut app, KeyCode::Return);
+        assert!(!app.world.resource::<Paused>().0);
+
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(8, 6)]);
+    }
+
+    #[test]
+    fn headless_debug_single_step_runs_one_tick_of_a_paused_game() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::RIGHT);
+        app.world.resource_mut::<Paused>().0 = true;
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(8, 6)]);
+
+        app.world.resource_mut::<DebugStep>().step_once = true;
+        app.update();
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+        assert!(!app.world.resource::<DebugStep>().step_once);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+    }
+
+    #[test]
+    fn headless_debug_slow_motion_stretches_the_steps() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::RIGHT);
+        app.world.resource_mut::<DebugStep>().slow_motion = true;
+        for _ in 1..SLOW_MOTION_FACTOR as usize {
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app), vec![(8, 6)]);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+    }
+
+    #[test]
+    fn headless_rebound_pause_key_pauses() {
+        let mut app = headless_app();
+        app.insert_resource(KeyBindings {
+            pause: KeyCode::K,
+            ..Default::default()
+        });
+        tap(&mut app, KeyCode::P);
+        assert!(!app.world.resource::<Paused>().0);
+        tap(&mut app, KeyCode::K);
+        assert!(app.world.resource::<Paused>().0);
+        tap(&mut app, KeyCode::Escape);
+        assert!(!app.world.resource::<Paused>().0);
+    }
+
+    #[test]
+    fn headless_pause_menu_quits_to_the_menu_and_out() {
+        let mut app = pause_menu_app();
+        tap(&mut app, KeyCode::Down);
+        tap(&mut app, KeyCode::Down);
+        tap(&mut app, KeyCode::Return);
+        assert_eq!(
+            *app.world.resource::<State<AppState>>().current(),
+            AppState::Menu
+        );
+        assert!(!app.world.resource::<Paused>().0);
+        assert!(snake_segments(&mut app, Player(0)).is_empty());
+        assert!(app.world.resource::<OccupiedCells>().counts.is_empty());
+
+        // Up from the top wraps around to Quit
+        let mut app = pause_menu_app();
+        tap(&mut app, KeyCode::Up);
+        tap(&mut app, KeyCode::Return);
+        assert!(!app.world.resource::<Events<AppExit>>().is_empty());
+    }
+
+    #[test]
+    fn headless_reversal_is_refused_across_a_pause() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+
+        // Left held through the pause and after it is still a turn straight back
+        app.world.resource_mut::<Paused>().0 = true;
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Left);
+        app.update();
+        app.world.resource_mut::<Paused>().0 = false;
+        app.update();
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(10, 6)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn headless_time_attack_ends_the_round_when_time_is_up() {
+        let mut app = restarted_with(headless_app(), TimeLimit(Some(1.)));
+        app.world.resource_mut::<GameClock>().seconds += 0.6;
+        app.update();
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+
+        // A pause doesn't eat into the time
+        app.world.resource_mut::<Paused>().0 = true;
+        app.world.resource_mut::<GameClock>().seconds += 5.;
+        app.update();
+        app.world.resource_mut::<Paused>().0 = false;
+        app.update();
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+
+        app.world.resource_mut::<GameClock>().seconds += 0.5;
+        app.update();
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+        assert!(!app.world.resource::<Events<GameOver>>().is_empty());
+
+        // The next round gets the whole time again
+        let app = restarted_with(app, TimeLimit(Some(1.)));
+        assert_eq!(app.world.resource::<TimeRemaining>().seconds, Some(1.));
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    fn two_player_app() -> App {
+        restarted_with(headless_app(), PlayerCount(2))
+    }
+
+    fn countdown_app() -> App {
+        restarted_with(headless_app(), CountdownLength(1.))
+    }
+
+    #[test]
+    fn headless_countdown_holds_the_snake_but_keeps_the_turn() {
+        let mut app = countdown_app();
+        steer(&mut app, Direction::UP);
+        for _ in 0..3 {
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app), vec![(8, 6)]);
+
+        // The first step comes a full interval after the countdown, in the queued direction
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(8, 6)]);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(8, 7)]);
+    }
+
+    #[test]
+    fn headless_countdown_sets_off_an_idle_snake() {
+        let mut app = countdown_app();
+        for _ in 0..5 {
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+    }
+
+    #[test]
+    fn headless_start_direction_sets_the_snake_off_at_once() {
+        let mut app = restarted_with(headless_app(), StartDirection(Direction::RIGHT));
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+
+        // Already moving right, so turning straight back is still refused
+        steer(&mut app, Direction::LEFT);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(10, 6)]);
+        steer(&mut app, Direction::DOWN);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(10, 5)]);
+    }
+
+    #[test]
+    fn headless_second_snake_starts_the_other_way() {
+        let app = restarted_with(two_player_app(), StartDirection(Direction::UP));
+        let mut app = restarted_with(app, StartLength(2));
+        assert_eq!(player_cells(&mut app, Player(0)), vec![(8, 6), (8, 5)]);
+        assert_eq!(player_cells(&mut app, Player(1)), vec![(7, 5), (7, 6)]);
+
+        step(&mut app);
+        assert_eq!(player_cells(&mut app, Player(0)), vec![(8, 7), (8, 6)]);
+        assert_eq!(player_cells(&mut app, Player(1)), vec![(7, 4), (7, 5)]);
+    }
+
+    #[test]
+    fn headless_u_turn_guard_drops_the_second_quick_turn() {
+        let mut app = restarted_with(headless_app(), StartDirection(Direction::RIGHT));
+        app.insert_resource(UTurnGuard(true));
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Up);
+        app.update();
+        let mut keyboard = app.world.resource_mut::<Input<KeyCode>>();
+        keyboard.release(KeyCode::Up);
+        keyboard.press(KeyCode::Left);
+        app.update();
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .release(KeyCode::Left);
+
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(8, 7)]);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(8, 8)]);
+    }
+
+    #[test]
+    fn headless_early_turns_step_a_turn_in_right_away() {
+        let mut app = headless_app();
+        app.insert_resource(EarlyTurns(true));
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+
+        let interval = app.world.resource::<StepInterval>().seconds;
+        let advance = |app: &mut App, fraction: f64| {
+            app.world.resource_mut::<GameClock>().seconds += interval * fraction;
+            app.update();
+        };
+        // Going straight waits for the regular step
+        advance(&mut app, 0.6);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6)]);
+
+        // A turn past the minimum interval is stepped in on the next frame
+        steer(&mut app, Direction::UP);
+        advance(&mut app, 0.);
+        assert_eq!(snake_cells(&mut app), vec![(9, 7)]);
+
+        // And the step after it waits a full interval again
+        advance(&mut app, 0.6);
+        assert_eq!(snake_cells(&mut app), vec![(9, 7)]);
+        advance(&mut app, 0.5);
+        assert_eq!(snake_cells(&mut app), vec![(9, 8)]);
+    }
+
+    #[test]
+    fn headless_computer_snake_goes_for_the_food() {
+        let mut app = restarted_with(headless_app(), AiEnabled(true));
+        assert_eq!(player_cells(&mut app, Player(1)), vec![(7, 5)]);
+
+        // Right along row 5, then up to the food at (9, 7), passing the idle player's head
+        for _ in 0..4 {
+            step(&mut app);
+        }
+        assert_eq!(player_cells(&mut app, Player(1)), vec![(9, 7)]);
+        assert!(app.world.resource::<Score>().value > 0);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn headless_two_snakes_move_independently() {
+        let mut app = two_player_app();
+        assert_eq!(player_cells(&mut app, Player(0)), vec![(8, 6)]);
+        assert_eq!(player_cells(&mut app, Player(1)), vec![(7, 5)]);
+
+        steer_player(&mut app, Player(0), Direction::UP);
+        steer_player(&mut app, Player(1), Direction::DOWN);
+        step(&mut app);
+        assert_eq!(player_cells(&mut app, Player(0)), vec![(8, 7)]);
+        assert_eq!(player_cells(&mut app, Player(1)), vec![(7, 4)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn headless_biting_yourself_in_truncate_mode_drops_the_tail() {
+        let mut app = headless_app();
+        app.insert_resource(StartLength(6));
+        let mut app = restarted_with(app, SelfCollisionMode::Truncate);
+        for direction in [Direction::UP, Direction::LEFT] {
+            steer(&mut app, direction);
+            step(&mut app);
+        }
+        let segments = snake_segments(&mut app, Player(0));
+        assert_eq!(
+            snake_cells(&mut app),
+            vec![(7, 7), (8, 7), (8, 6), (7, 6), (6, 6), (5, 6)]
+        );
+
+        // Down onto (7, 6), the fifth segment: it and the one behind it are gone
+        steer(&mut app, Direction::DOWN);
+        step(&mut app);
+        assert_eq!(snake_segments(&mut app, Player(0)), segments[..4]);
+        assert_eq!(snake_cells(&mut app), vec![(7, 6), (7, 7), (8, 7), (8, 6)]);
+        for entity in &segments[4..] {
+            assert!(app.world.get_entity(*entity).is_none());
+        }
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+        assert_eq!(occupied_cells(&app), vec![(7, 6), (7, 7), (8, 6), (8, 7)]);
+    }
+
+    #[test]
+    fn headless_snakes_running_into_each_other_end_the_round() {
+        let mut app = two_player_app();
+        steer_player(&mut app, Player(0), Direction::DOWN);
+        steer_player(&mut app, Player(1), Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(player_cells(&mut app, Player(0)), vec![(8, 5)]);
+        assert_eq!(player_cells(&mut app, Player(1)), vec![(8, 5)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+    }
+
+    #[test]
+    fn headless_snake_grows_after_eating() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        assert!(app.world.resource::<Score>().value > 0);
+        // The food was moved to a random cell, keep it off the path below
+        let mut query = app.world.query_filtered::<&mut GridPos, With<Food>>();
+        for mut pos in query.iter_mut(&mut app.world) {
+            *pos = cell(0, 0);
+        }
+
+        // The new tail appears a few steps later, once the body has moved off the food cell
+        for _ in 0..3 {
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app), vec![(9, 10), (9, 9)]);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 11), (9, 10)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn headless_grown_segments_grow_in_but_take_their_cell_at_once() {
+        let mut app = restarted_with(headless_app(), StartLength(2));
+        let starting_tail = snake_segments(&mut app, Player(0))[1];
+        assert!(app.world.get::<GrowIn>(starting_tail).is_none());
+        assert_eq!(
+            app.world.get::<Transform>(starting_tail).unwrap().scale,
+            Vec3::ONE
+        );
+
+        // Right onto the food at (9, 7) by way of (9, 6)
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        let mut query = app.world.query_filtered::<&mut GridPos, With<Food>>();
+        for mut pos in query.iter_mut(&mut app.world) {
+            *pos = cell(0, 0);
+        }
+        for _ in 0..4 {
+            step(&mut app);
+        }
+        assert_eq!(snake_segments(&mut app, Player(0)).len(), 3);
+
+        let grown = *snake_segments(&mut app, Player(0)).last().unwrap();
+        assert!(app.world.get::<GrowIn>(grown).is_some());
+        assert_eq!(app.world.get::<Transform>(grown).unwrap().scale, Vec3::ZERO);
+        let grown_cell = app.world.get::<GridPos>(grown).unwrap().cell();
+        assert!(occupied_cells(&app).contains(&grown_cell));
+    }
+
+    fn food_eaten_events(app: &App) -> Vec<FoodEaten> {
+        let events = app.world.resource::<Events<FoodEaten>>();
+        events.get_reader().iter(events).copied().collect()
+    }
+
+    #[test]
+    fn headless_eating_sends_food_eaten() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert!(food_eaten_events(&app).is_empty());
+
+        // The food goes on losing value up to the step that eats it
+        let mut query = app.world.query_filtered::<&ValueTimer, With<Food>>();
+        let mut value_timer = ValueTimer {
+            ticks: query.iter(&app.world).next().unwrap().ticks,
+        };
+        value_timer.tick();
+        let points = value_timer.value().floor() as u32;
+
+        // Up from (9, 6) onto the food at (9, 7)
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        assert_eq!(
+            food_eaten_events(&app),
+            vec![FoodEaten {
+                player: Player(0),
+                cell: (9, 7),
+                points,
+                golden: false,
+            }]
+        );
+        assert_eq!(app.world.resource::<Score>().value, points);
+    }
+
+    #[test]
+    fn headless_snake_starts_pre_grown() {
+        let mut app = restarted_with(headless_app(), StartLength(3));
+        assert_eq!(snake_cells(&mut app), vec![(8, 6), (7, 6), (6, 6)]);
+
+        // Standing still doesn't fold the body onto the head
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(8, 6), (7, 6), (6, 6)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+
+        // Turning back into the body is rejected, the body then follows the head
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Left);
+        app.update();
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .release(KeyCode::Left);
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(8, 7), (8, 6), (7, 6)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    fn food_cells(app: &mut App) -> Vec<(i32, i32)> {
+        let mut query = app.world.query_filtered::<&GridPos, With<Food>>();
+        query.iter(&app.world).map(|pos| pos.cell()).collect()
+    }
+
+    #[test]
+    fn headless_replay_plays_a_recorded_round_again() {
+        let turns = [
+            Direction::RIGHT,
+            Direction::UP,
+            Direction::UP,
+            Direction::LEFT,
+            Direction::LEFT,
+            Direction::DOWN,
+        ];
+        let mut app = restarted_with(headless_app(), FoodCount(3));
+        for direction in turns {
+            steer(&mut app, direction);
+            step(&mut app);
+        }
+        let recording = app.world.resource::<Recording>().clone();
+        assert_eq!(recording.moves.len(), turns.len());
+        assert_eq!(recording.moves[1], vec![Direction::UP]);
+
+        let replay = restarted_with(headless_app(), FoodCount(3));
+        let mut replay = restarted_with(
+            replay,
+            Playback {
+                recording: Some(recording.clone()),
+                step: 0,
+            },
+        );
+        for _ in turns {
+            step(&mut replay);
+        }
+        assert_eq!(snake_cells(&mut replay), snake_cells(&mut app));
+        let mut replayed_foods = food_cells(&mut replay);
+        let mut recorded_foods = food_cells(&mut app);
+        replayed_foods.sort();
+        recorded_foods.sort();
+        assert_eq!(replayed_foods, recorded_foods);
+        assert_eq!(
+            replay.world.resource::<Score>().value,
+            app.world.resource::<Score>().value
+        );
+        assert_eq!(*replay.world.resource::<Recording>(), recording);
+    }
+
+    #[test]
+    fn headless_fixed_seed_places_food_the_same_every_time() {
+        let seeded_app = || {
+            let mut app = headless_app();
+            app.insert_resource(FoodCount(4));
+            restarted_with(app, RngSeed(Some(7)))
+        };
+        let mut first = seeded_app();
+        let mut second = seeded_app();
+        let sorted_food_cells = |app: &mut App| {
+            let mut cells = food_cells(app);
+            cells.sort();
+            cells
+        };
+        assert_eq!(
+            sorted_food_cells(&mut first),
+            sorted_food_cells(&mut second)
+        );
+
+        // Eating moves the food to the same cell too
+        for app in [&mut first, &mut second] {
+            steer(app, Direction::RIGHT);
+            step(app);
+            steer(app, Direction::UP);
+            step(app);
+            assert!(app.world.resource::<Score>().value > 0);
+        }
+        assert_eq!(
+            sorted_food_cells(&mut first),
+            sorted_food_cells(&mut second)
+        );
+    }
+
+    #[test]
+    fn headless_board_holds_several_foods() {
+        let mut app = restarted_with(headless_app(), FoodCount(3));
+        let foods = food_cells(&mut app);
+        assert_eq!(foods.len(), 3);
+        assert!(foods.contains(&(9, 7)));
+        let distinct: HashSet<(i32, i32)> = foods.iter().copied().collect();
+        assert_eq!(distinct.len(), 3);
+        assert!(!distinct.contains(&(8, 6)));
+
+        // Pin the random foods into a corner so the path below can't eat them
+        let mut query = app.world.query_filtered::<&mut GridPos, With<Food>>();
+        let mut corner = 0;
+        for mut pos in query.iter_mut(&mut app.world) {
+            if *pos != cell(9, 7) {
+                *pos = cell(corner, 0);
+                corner += 1;
+            }
+        }
+        let foods = food_cells(&mut app);
+
+        // Eating the food at (9, 7) only moves that one
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        assert!(app.world.resource::<Score>().value > 0);
+        let after = food_cells(&mut app);
+        assert_eq!(after.len(), 3);
+        assert!(!after.contains(&(9, 7)));
+        let kept: Vec<&(i32, i32)> = foods.iter().filter(|&&cell| cell != (9, 7)).collect();
+        assert!(kept.iter().all(|cell| after.contains(cell)));
+        let distinct: HashSet<(i32, i32)> = after.into_iter().collect();
+        assert_eq!(distinct.len(), 3);
+    }
+
+    fn spawn_golden_food(app: &mut App, x: i32, y: i32, ticks: u32) -> Entity {
+        app.world
+            .spawn()
+            .insert(cell(x, y))
+            .insert(Sprite::default())
+            .insert(ValueTimer::new())
+            .insert(Food)
+            .insert(Golden)
+            .insert(Lifetime::new(ticks))
+            .id()
+    }
+
+    #[test]
+    fn headless_golden_food_pays_a_bonus_and_grows_by_two() {
+        let mut app = headless_app();
+        let golden = spawn_golden_food(&mut app, 9, 6, GOLDEN_FOOD_TICKS);
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(
+            app.world.resource::<Score>().value,
+            FOOD_START_VALUE as u32 + GOLDEN_FOOD_BONUS
+        );
+        assert!(app.world.get_entity(golden).is_none());
+        // The regular food is still where it started
+        assert_eq!(food_cells(&mut app), vec![(9, 7)]);
+
+        for _ in 0..6 {
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app).len(), 1 + GOLDEN_FOOD_GROWTH as usize);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn headless_golden_food_expires() {
+        let mut app = headless_app();
+        let golden = spawn_golden_food(&mut app, 0, 0, 2);
+        step(&mut app);
+        assert!(app.world.get_entity(golden).is_some());
+        step(&mut app);
+        assert!(app.world.get_entity(golden).is_none());
+    }
+
+    fn spawn_rotten_food(app: &mut App, x: i32, y: i32) -> Entity {
+        app.world
+            .spawn()
+            .insert(cell(x, y))
+            .insert(Sprite::default())
+            .insert(ValueTimer::new())
+            .insert(Food)
+            .insert(Rotten)
+            .insert(Lifetime::new(ROTTEN_FOOD_TICKS))
+            .id()
+    }
+
+    #[test]
+    fn headless_rotten_food_ends_the_round() {
+        let mut app = headless_app();
+        spawn_rotten_food(&mut app, 9, 6);
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+        assert_eq!(app.world.resource::<Score>().value, 0);
+    }
+
+    #[test]
+    fn headless_rotten_food_in_truncate_mode_costs_the_tail_end() {
+        let mut app = headless_app();
+        app.insert_resource(StartLength(4));
+        let mut app = restarted_with(app, SelfCollisionMode::Truncate);
+        let rotten = spawn_rotten_food(&mut app, 9, 6);
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6), (8, 6)]);
+        assert!(app.world.get_entity(rotten).is_none());
+        assert_eq!(occupied_cells(&app), vec![(8, 6), (9, 6)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+
+        // With nothing left to lose it's the end of the round after all
+        spawn_rotten_food(&mut app, 11, 6);
+        step(&mut app);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(11, 6)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+        spawn_rotten_food(&mut app, 12, 6);
+        step(&mut app);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+    }
+
+    #[test]
+    fn headless_running_into_an_obstacle_ends_the_round() {
+        let level_layout = LevelLayout {
+            walls: vec![(10, 6), (0, 0)],
+            ..Default::default()
+        };
+        let mut app = restarted_with(headless_app(), level_layout);
+        let mut query = app.world.query_filtered::<(), With<Wall>>();
+        assert_eq!(query.iter(&app.world).count(), 2);
+
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+        step(&mut app);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+    }
+
+    #[test]
+    fn headless_portal_takes_the_head_to_its_partner() {
+        let level_layout = LevelLayout {
+            portals: vec![(10, 6), (3, 2)],
+            ..Default::default()
+        };
+        let mut app = headless_app();
+        app.insert_resource(StartLength(3));
+        let mut app = restarted_with(app, level_layout);
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(9, 6), (8, 6), (7, 6)]);
+
+        // Into the portal at (10, 6), out at (3, 2) still heading right, the body after it
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(3, 2), (9, 6), (8, 6)]);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(4, 2), (3, 2), (9, 6)]);
+        step(&mut app);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app), vec![(6, 2), (5, 2), (4, 2)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn headless_food_never_lands_on_an_obstacle() {
+        // Everything but row 6 and the first food's cell is wall
+        let walls = (0..16)
+            .flat_map(|x| (0..12).map(move |y| (x, y)))
+            .filter(|&(x, y)| y != 6 && (x, y) != (9, 7))
+            .collect();
+        let mut app = restarted_with(
+            headless_app(),
+            LevelLayout {
+                walls,
+                ..Default::default()
+            },
+        );
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+        assert!(app.world.resource::<Score>().value > 0);
+
+        let foods = food_cells(&mut app);
+        assert_eq!(foods.len(), 1);
+        assert_eq!(foods[0].1, 6);
+    }
+
+    #[test]
+    fn headless_round_starts_from_a_level_file() {
+        let level_layout = LevelLayout::parse(
+            "\
+            ....\n\
+            .F#.\n\
+            S...\n",
+        )
+        .unwrap();
+        let grid_config = GridConfig::new();
+        let board = level_layout.board().unwrap();
+        let mut app = App::new();
+        app.insert_resource(board)
+            .add_plugin(SimulationPlugin)
+            .insert_resource(level_layout);
+        app.world
+            .resource_mut::<State<AppState>>()
+            .set(AppState::InGame)
+            .unwrap();
+        app.update();
+
+        assert_eq!(snake_cells(&mut app), vec![(0, 0)]);
+        assert_eq!(food_cells(&mut app), vec![(1, 1)]);
+        let mut wall_query = app.world.query_filtered::<&GridPos, With<Wall>>();
+        let walls: Vec<(i32, i32)> = wall_query.iter(&app.world).map(|pos| pos.cell()).collect();
+        assert_eq!(walls, vec![(2, 1)]);
+
+        // The sprites are drawn on the same cells
+        let head = snake_segments(&mut app, Player(0))[0];
+        let head = app.world.get::<Transform>(head).unwrap().translation;
+        assert_eq!(grid_config.world_to_grid(head, &board), (0, 0));
+    }
+
+    #[test]
+    fn choosing_a_mode_keeps_the_theme() {
+        let mut app = App::new();
+        app.init_resource::<Input<KeyCode>>()
+            .insert_resource(TimeLimit(None))
+            .insert_resource(Objective::new(0))
+            .insert_resource(CollisionsEnabled(true))
+            .insert_resource(Theme::default())
+            .add_system(cycle_theme)
+            .add_system(choose_mode);
+        tap(&mut app, KeyCode::Tab);
+        assert_eq!(
+            app.world.resource::<TimeLimit>().0,
+            Some(TIME_ATTACK_SECONDS)
+        );
+        assert_eq!(app.world.resource::<Theme>().name, Theme::default().name);
+    }
+
+    #[test]
+    fn view_scale_keeps_the_board_in_sight() {
+        let needed = WinSize { w: 800., h: 600. };
+        let fit = |w, h| view_scale(&needed, &WinSize { w, h }, ViewMode::Fit);
+        assert_eq!(fit(800., 600.), 1.);
+        // Narrower window, the width decides and the height is letterboxed
+        assert_eq!(fit(400., 600.), 2.);
+        assert_eq!(fit(1600., 600.), 1.);
+        assert_eq!(fit(1600., 1200.), 0.5);
+        // 1:1 pixels, however little or much of the window the board takes
+        let pixels = |w, h| view_scale(&needed, &WinSize { w, h }, ViewMode::Pixels);
+        assert_eq!(pixels(400., 600.), 1.);
+        assert_eq!(pixels(1600., 1200.), 1.);
+    }
+
+    #[test]
+    fn camera_refits_when_the_window_or_view_mode_changes() {
+        let mut app = App::new();
+        let grid_config = GridConfig::new();
+        let board = Board { cols: 16, rows: 12 };
+        let needed = board.win_size(&grid_config, BOARD_MARGIN);
+        app.insert_resource(board)
+            .insert_resource(grid_config)
+            .insert_resource(WinSize {
+                w: needed.w / 2.,
+                h: needed.h,
+            })
+            .insert_resource(ViewMode::Fit)
+            .add_system(fit_camera);
+        let camera = app
+            .world
+            .spawn()
+            .insert(OrthographicProjection::default())
+            .insert(GameCamera)
+            .id();
+        let scale = |app: &App| {
+            app.world
+                .get::<OrthographicProjection>(camera)
+                .unwrap()
+                .scale
+        };
+        app.update();
+        assert_eq!(scale(&app), 2.);
+
+        app.world.resource_mut::<WinSize>().w = needed.w * 2.;
+        app.update();
+        assert_eq!(scale(&app), 1.);
+
+        app.insert_resource(ViewMode::Pixels);
+        app.world.resource_mut::<WinSize>().w = needed.w / 2.;
+        app.update();
+        assert_eq!(scale(&app), 1.);
+    }
+
+    #[test]
+    fn grid_tiles_cover_the_board_cell_by_cell() {
+        let board = Board { cols: 5, rows: 3 };
+        let grid_config = GridConfig::new();
+        let mut app = App::new();
+        app.insert_resource(board)
+            .insert_resource(GridConfig::new())
+            .insert_resource(ShowGrid(false))
+            .insert_resource(Theme::default())
+            .add_startup_system(setup_grid);
+        app.update();
+
+        let mut query = app
+            .world
+            .query_filtered::<(&Transform, &Visibility), With<GridTile>>();
+        let cells: HashSet<(i32, i32)> = query
+            .iter(&app.world)
+            .map(|(transform, visibility)| {
+                assert!(!visibility.is_visible);
+                assert!(transform.translation.z < FOOD_LAYER);
+                grid_config.world_to_grid(transform.translation, &board)
+            })
+            .collect();
+        assert_eq!(cells.len(), 15);
+        assert!(cells.iter().all(|&cell| board.contains(cell)));
+    }
+
+    fn applied_settings(level_layout: LevelLayout, difficulty: Difficulty) -> App {
+        let mut settings = Settings::new(Board { cols: 16, rows: 12 });
+        settings.adjust(OptionField::Speed, true);
+        settings.adjust(OptionField::Columns, true);
+        settings.adjust(OptionField::Boundary, true);
+        settings.adjust(OptionField::SelfCollision, true);
+        settings.adjust(OptionField::Food, true);
+        settings.adjust(OptionField::Scoring, true);
+        let mut app = App::new();
+        app.insert_resource(settings)
+            .insert_resource(level_layout)
+            .insert_resource(Board { cols: 16, rows: 12 })
+            .insert_resource(BoundaryMode::Wall)
+            .insert_resource(SelfCollisionMode::Die)
+            .insert_resource(ScoringMode::Points)
+            .insert_resource(CollisionsEnabled(true))
+            .insert_resource(FoodCount(FOOD_COUNT))
+            .insert_resource(StartInterval(TIME_STEP as f64))
+            .insert_resource(difficulty)
+            .add_system(apply_settings);
+        app.update();
+        app
+    }
+
+    #[test]
+    fn settings_are_written_into_the_round_resources() {
+        let app = applied_settings(LevelLayout::default(), Difficulty::Normal);
+        assert_eq!(*app.world.resource::<Board>(), Board { cols: 17, rows: 12 });
+        assert_eq!(*app.world.resource::<BoundaryMode>(), BoundaryMode::Wrap);
+        assert_eq!(
+            *app.world.resource::<SelfCollisionMode>(),
+            SelfCollisionMode::Truncate
+        );
+        assert_eq!(app.world.resource::<FoodCount>().0, FOOD_COUNT + 1);
+        assert_eq!(*app.world.resource::<ScoringMode>(), ScoringMode::Length);
+        assert_eq!(app.world.resource::<StartInterval>().0, SPEED_INTERVALS[3]);
+
+        // A level's own board size wins over the options
+        let level_layout = LevelLayout::parse("#####\n#S..#\n#####").unwrap();
+        let app = applied_settings(level_layout, Difficulty::Hard);
+        assert_eq!(*app.world.resource::<Board>(), Board { cols: 16, rows: 12 });
+        assert_eq!(*app.world.resource::<BoundaryMode>(), BoundaryMode::Wrap);
+
+        let app = applied_settings(LevelLayout::default(), Difficulty::Hard);
+        assert_eq!(*app.world.resource::<Board>(), Board { cols: 12, rows: 9 });
+    }
+
+    #[test]
+    fn head_faces_its_direction() {
+        let facing = |direction| facing_rotation(direction) * Vec3::X;
+        assert!(facing(Direction::RIGHT).abs_diff_eq(Vec3::X, 1e-6));
+        assert!(facing(Direction::UP).abs_diff_eq(Vec3::Y, 1e-6));
+        assert!(facing(Direction::LEFT).abs_diff_eq(-Vec3::X, 1e-6));
+        assert!(facing(Direction::DOWN).abs_diff_eq(-Vec3::Y, 1e-6));
+        assert_eq!(
+            facing_rotation(Direction::NONE),
+            facing_rotation(START_DIRECTION)
+        );
+    }
+
+    #[test]
+    fn motion_records_the_last_step_of_each_segment() {
+        let mut app = restarted_with(headless_app(), StartLength(2));
+        app.add_system_set(
+            SystemSet::new()
+                .with_run_criteria(StepCriteria)
+                .with_system(track_motion.after(Labels::HeadMove)),
+        );
+        steer(&mut app, Direction::UP);
+        step(&mut app);
+
+        let board = Board { cols: 16, rows: 12 };
+        let grid_config = GridConfig::new();
+        let segments = snake_segments(&mut app, Player(0));
+        let steps: Vec<((i32, i32), (i32, i32))> = segments
+            .iter()
+            .map(|&entity| {
+                let motion = app.world.get::<Motion>(entity).unwrap();
+                (
+                    grid_config.world_to_grid(motion.from, &board),
+                    grid_config.world_to_grid(motion.to, &board),
+                )
+            })
+            .collect();
+        assert_eq!(steps, vec![((8, 6), (8, 7)), ((7, 6), (8, 6))]);
+    }
+
+    #[test]
+    fn steps_to_edge_counts_to_the_side_ahead() {
+        let board = Board { cols: 16, rows: 12 };
+        assert_eq!(
+            steps_to_edge(cell(13, 6), Direction::RIGHT, &board),
+            Some(2)
+        );
+        assert_eq!(
+            steps_to_edge(cell(13, 6), Direction::LEFT, &board),
+            Some(13)
+        );
+        assert_eq!(steps_to_edge(cell(4, 11), Direction::UP, &board), Some(0));
+        assert_eq!(
+            steps_to_edge(cell(4, 11), Direction::DOWN, &board),
+            Some(11)
+        );
+        assert_eq!(steps_to_edge(cell(4, 11), Direction::NONE, &board), None);
+    }
+
+    #[test]
+    fn headless_wrap_warning_lights_the_edge_ahead() {
+        let mut app = headless_app();
+        app.insert_resource(BoundaryMode::Wrap)
+            .add_system(glow_wrap_edges.after(Labels::HeadMove));
+        steer(&mut app, Direction::RIGHT);
+        let lit_sides = |app: &mut App| -> Vec<Direction> {
+            let mut query = app.world.query::<(&EdgeGlow, &Visibility)>();
+            query
+                .iter(&app.world)
+                .filter(|(_, visibility)| visibility.is_visible)
+                .map(|(edge, _)| edge.side)
+                .collect()
+        };
+        for _ in 0..4 {
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app), vec![(12, 6)]);
+        assert!(lit_sides(&mut app).is_empty());
+
+        step(&mut app);
+        assert_eq!(lit_sides(&mut app), vec![Direction::RIGHT]);
+
+        app.insert_resource(BoundaryMode::Wall);
+        app.update();
+        assert!(lit_sides(&mut app).is_empty());
+    }
+
+    #[test]
+    fn cell_overlay_lists_snake_ends_and_foods() {
+        let snakes = vec![
+            (Player(0), vec![cell(8, 6), cell(7, 6), cell(6, 6)]),
+            (Player(1), vec![cell(2, 3)]),
+        ];
+        assert_eq!(
+            cell_overlay_lines(&snakes, &[cell(9, 7), cell(0, 0)]),
+            "P1 head: (8, 6)  tail: (6, 6)  length: 3\n\
+             P2 head: (2, 3)  tail: (2, 3)  length: 1\n\
+             Food: (9, 7) (0, 0)"
+        );
+    }
+
+    #[test]
+    fn headless_head_steps_by_the_cell_size_of_each_axis() {
+        let mut app = restarted_with(
+            headless_app(),
+            GridConfig {
+                cell_w: 40.,
+                cell_h: 20.,
+            },
+        );
+        let head = snake_segments(&mut app, Player(0))[0];
+        let position = |app: &App| app.world.get::<Transform>(head).unwrap().translation;
+        let start = position(&app);
+
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(position(&app) - start, Vec3::new(40., 0., 0.));
+        steer(&mut app, Direction::DOWN);
+        step(&mut app);
+        assert_eq!(position(&app) - start, Vec3::new(40., -20., 0.));
+        assert_eq!(snake_cells(&mut app), vec![(9, 5)]);
+    }
+
+    #[test]
+    fn headless_restart_waits_for_the_death_animation() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::LEFT);
+        for _ in 0..9 {
+            step(&mut app);
+        }
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+
+        app.insert_resource(DyingTimer(Timer::from_seconds(DEATH_SECONDS, false)));
+        tap(&mut app, KeyCode::Space);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+
+        app.world.remove_resource::<DyingTimer>();
+        app.world.resource_mut::<GameClock>().seconds += RESTART_DELAY_SECONDS;
+        tap(&mut app, KeyCode::Space);
+        app.update();
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+        assert_eq!(snake_cells(&mut app), vec![(8, 6)]);
+    }
+
+    #[test]
+    fn headless_restart_key_held_through_the_crash_does_not_restart() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::LEFT);
+        // Held down from before the crash on
+        app.world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Space);
+        for _ in 0..9 {
+            step(&mut app);
+            app.world.resource_mut::<Input<KeyCode>>().clear();
+        }
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+        app.world.resource_mut::<GameClock>().seconds += RESTART_DELAY_SECONDS;
+        app.update();
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+
+        // Nor does a press right as the round ends
+        let mut app = headless_app();
+        steer(&mut app, Direction::LEFT);
+        for _ in 0..9 {
+            step(&mut app);
+        }
+        tap(&mut app, KeyCode::Space);
+        app.update();
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+
+        // Let go and pressed again once the round has settled, it restarts
+        app.world.resource_mut::<GameClock>().seconds += RESTART_DELAY_SECONDS;
+        tap(&mut app, KeyCode::Space);
+        app.update();
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn headless_dash_moves_two_cells_then_cools_down() {
+        let mut app = restarted_with(headless_app(), StartLength(4));
+        steer(&mut app, Direction::RIGHT);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app)[0], (9, 6));
+
+        tap(&mut app, KeyCode::LShift);
+        step(&mut app);
+        let cells = snake_cells(&mut app);
+        assert_eq!(cells[0], (11, 6));
+        // The body closes up behind the head, no cell skipped
+        assert!(cells.windows(2).all(|pair| {
+            let ((x, y), (next_x, next_y)) = (pair[0], pair[1]);
+            (x - next_x).abs() + (y - next_y).abs() == 1
+        }));
+
+        // Too soon for another one
+        tap(&mut app, KeyCode::LShift);
+        step(&mut app);
+        assert_eq!(snake_cells(&mut app)[0], (12, 6));
+        let clock = app.world.resource::<GameClock>().seconds;
+        assert!(!app.world.resource::<Dash>().ready(Player(0), clock));
+    }
+
+    #[test]
+    fn dash_label_counts_down_whole_seconds() {
+        let mut dash = Dash::default();
+        assert_eq!(dash.label(Player(0), 10.), "ready");
+        dash.ready_at.insert(Player(0), 12.5);
+        assert_eq!(dash.label(Player(0), 10.), "3s");
+        assert_eq!(dash.label(Player(0), 12.), "1s");
+        assert_eq!(dash.label(Player(0), 13.), "ready");
+        assert!(dash.ready(Player(1), 10.));
+    }
+
+    #[test]
+    fn headless_practice_runs_through_walls_and_itself() {
+        let mut app = restarted_with(headless_app(), StartLength(5));
+        app.insert_resource(BoundaryMode::Wrap);
+        let mut app = restarted_with(app, CollisionsEnabled(false));
+        // Around in a tight square onto its own body
+        for direction in [Direction::UP, Direction::LEFT, Direction::DOWN] {
+            steer(&mut app, direction);
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app)[0], (7, 6));
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+
+        // And across the edge to come back on the other side
+        steer(&mut app, Direction::LEFT);
+        for _ in 0..8 {
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app)[0], (15, 6));
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+    }
+
+    #[test]
+    fn practice_plays_with_the_board_edges_wrapping() {
+        let mut app = applied_settings(LevelLayout::default(), Difficulty::Normal);
+        app.world
+            .resource_mut::<Settings>()
+            .adjust(OptionField::Boundary, true);
+        app.insert_resource(CollisionsEnabled(false));
+        app.update();
+        assert_eq!(*app.world.resource::<BoundaryMode>(), BoundaryMode::Wrap);
+    }
+
+    #[test]
+    fn headless_wall_hit_ends_the_round() {
+        let mut app = headless_app();
+        steer(&mut app, Direction::LEFT);
+        for _ in 0..8 {
+            step(&mut app);
+        }
+        assert_eq!(snake_cells(&mut app), vec![(0, 6)]);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::Running);
+
+        step(&mut app);
+        assert_eq!(*app.world.resource::<GameState>(), GameState::GameOver);
+    }
+
+    #[test]
+    fn simulation_runs_under_minimal_plugins() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(Board { cols: 16, rows: 12 })
+            .add_plugin(SimulationPlugin);
+        app.update();
+        app.world
+            .resource_mut::<State<AppState>>()
+            .set(AppState::InGame)
+            .unwrap();
+        app.update();
+        assert_eq!(snake_cells(&mut app).len(), 1);
+    }
+}
@@ -0,0 +1,1688 @@
+use crate::components::{Direction, GridPos, Player};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs;
+use std::io;
+
+// /* Enums
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppState {
+    // The title shown for a moment at start-up, before the menu
+    Splash,
+    Menu,
+    Options,
+    InGame,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryMode {
+    Wall,
+    Wrap,
+}
+// What running into its own body does to a snake: end the round, or bite off the
+// segment it hit and everything behind it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfCollisionMode {
+    Die,
+    Truncate,
+}
+// What the score counts: the points of the foods eaten, or how long the snakes are, one
+// for each segment behind a head, so the score and what's on the board always agree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoringMode {
+    Points,
+    Length,
+}
+// How big the board is drawn: scaled to just fit the window, or at one world unit to a
+// screen pixel for a crisp view, cropped or with room around it as the window has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    Fit,
+    Pixels,
+}
+// Whether a held key keeps steering, or only the press itself counts as one turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    Held,
+    Tapped,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameState {
+    Running,
+    GameOver,
+    Victory,
+}
+// Bundles of starting parameters, picked in the menu and applied when a round starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+impl Difficulty {
+    pub fn next(&self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+    // Scales the starting step interval chosen in the options
+    pub fn interval_scale(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 1.25,
+            Difficulty::Normal => 1.,
+            Difficulty::Hard => 0.75,
+        }
+    }
+    // How short eating can make the step
+    pub fn interval_floor(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.12,
+            Difficulty::Normal => STEP_INTERVAL_FLOOR,
+            Difficulty::Hard => 0.05,
+        }
+    }
+    // The biggest board played on, None for whatever the options say
+    pub fn max_board(&self) -> Option<Board> {
+        match self {
+            Difficulty::Hard => Some(Board { cols: 12, rows: 9 }),
+            _ => None,
+        }
+    }
+    pub fn step_interval(&self, start_interval: f64) -> StepInterval {
+        StepInterval {
+            seconds: (start_interval * self.interval_scale()).max(self.interval_floor()),
+            floor: self.interval_floor(),
+        }
+    }
+}
+// The lines of the options screen, top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionField {
+    Speed,
+    Columns,
+    Rows,
+    Boundary,
+    SelfCollision,
+    Food,
+    Scoring,
+}
+impl OptionField {
+    pub const ALL: [OptionField; 7] = [
+        OptionField::Speed,
+        OptionField::Columns,
+        OptionField::Rows,
+        OptionField::Boundary,
+        OptionField::SelfCollision,
+        OptionField::Food,
+        OptionField::Scoring,
+    ];
+}
+// The lines of the pause menu, top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseOption {
+    Resume,
+    Restart,
+    QuitToMenu,
+    Quit,
+}
+impl PauseOption {
+    pub const ALL: [PauseOption; 4] = [
+        PauseOption::Resume,
+        PauseOption::Restart,
+        PauseOption::QuitToMenu,
+        PauseOption::Quit,
+    ];
+    pub fn label(&self) -> &'static str {
+        match self {
+            PauseOption::Resume => "Resume",
+            PauseOption::Restart => "Restart",
+            PauseOption::QuitToMenu => "Quit to Menu",
+            PauseOption::Quit => "Quit",
+        }
+    }
+}
+// */ Enums
+
+// /*Game Constants
+pub const GRID_SIZE: f32 = 50.;
+pub const TIME_STEP: f32 = 0.25;
+pub const STEP_INTERVAL_FACTOR: f64 = 0.95;
+pub const STEP_INTERVAL_FLOOR: f64 = 0.08;
+pub const MAX_STEPS_PER_FRAME: u32 = 5;
+// With early turns on, a turn steps the snake early, but never before this much of the
+// step interval has passed. Keeps tapping keys from running the snake faster than a
+// step every half interval.
+pub const EARLY_STEP_FRACTION: f64 = 0.5;
+// How many times longer a step takes in debug slow motion
+pub const SLOW_MOTION_FACTOR: f64 = 4.;
+// Room kept around the board in the window, the border is drawn in it
+pub const BOARD_MARGIN: f32 = 10.;
+pub const START_LENGTH: usize = 1;
+pub const FOOD_COUNT: usize = 1;
+pub const GOLDEN_FOOD_CHANCE: f64 = 0.15;
+pub const ROTTEN_FOOD_CHANCE: f64 = 0.1;
+pub const COMBO_WINDOW_TICKS: u32 = 15;
+pub const COMBO_MAX_MULTIPLIER: u32 = 5;
+pub const COUNTDOWN_SECONDS: f64 = 3.;
+// Starting step intervals offered as speeds 1 to 5 on the options screen
+pub const SPEED_INTERVALS: [f64; 5] = [0.4, 0.32, 0.25, 0.18, 0.12];
+pub const MIN_BOARD_SIDE: u32 = 5;
+pub const MAX_BOARD_SIDE: u32 = 40;
+pub const MAX_FOOD_COUNT: usize = 5;
+pub const GO_SECONDS: f64 = 0.5;
+pub const TIME_ATTACK_SECONDS: f64 = 60.;
+pub const OBJECTIVE_FOODS: u32 = 20;
+// Seconds a snake waits after a dash before it can dash again
+pub const DASH_COOLDOWN_SECONDS: f64 = 3.;
+// Segments the stress test starts the first snake with, cut down to what fits the board
+pub const STRESS_LENGTH: usize = 500;
+// */Game Constants
+
+// /*Asset constants
+pub const HEAD_SCALE: f32 = 95. / 100.;
+pub const TAIL_SCALE: f32 = 85. / 100.;
+pub const TAIL_TIP_SCALE: f32 = 55. / 100.;
+// */Asset constants
+
+// /*Resources
+pub struct WinSize {
+    pub w: f32,
+    pub h: f32,
+}
+// The logical playfield in cells. It is centered in the window whatever the window's
+// pixel size, so it may leave a border around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Board {
+    pub cols: u32,
+    pub rows: u32,
+}
+impl Board {
+    // The biggest board that fits the window with `margin` pixels kept free on every side.
+    // A window too small for MIN_BOARD_SIDE cells a side still gets that many, there
+    // would be no room to play otherwise.
+    pub fn fit(win_size: &WinSize, grid_config: &GridConfig, margin: f32) -> Self {
+        let side = |cells: i32| cells.max(MIN_BOARD_SIDE as i32) as u32;
+        Board {
+            cols: side(grid_config.column_count(win_size.w - 2. * margin)),
+            rows: side(grid_config.row_count(win_size.h - 2. * margin)),
+        }
+    }
+    pub fn contains(&self, (cell_x, cell_y): (i32, i32)) -> bool {
+        (0..self.cols as i32).contains(&cell_x) && (0..self.rows as i32).contains(&cell_y)
+    }
+    // Brings a cell that left the board back in from the opposite edge
+    pub fn wrap(&self, pos: GridPos) -> GridPos {
+        GridPos::new(
+            pos.x.rem_euclid(self.cols as i32),
+            pos.y.rem_euclid(self.rows as i32),
+        )
+    }
+    // The window that shows the board with `margin` pixels around it
+    pub fn win_size(&self, grid_config: &GridConfig, margin: f32) -> WinSize {
+        WinSize {
+            w: self.cols as f32 * grid_config.cell_w + 2. * margin,
+            h: self.rows as f32 * grid_config.cell_h + 2. * margin,
+        }
+    }
+}
+// The grid cells snake segments and walls are on, kept up to date as the snakes move so a
+// head can be checked against them without going over every segment. Counted per cell, a
+// head that ran into something shares its cell and shows up as a count above one.
+#[derive(Debug, Default)]
+pub struct OccupiedCells {
+    pub counts: HashMap<(i32, i32), u32>,
+}
+impl OccupiedCells {
+    pub fn add(&mut self, cell: (i32, i32)) {
+        *self.counts.entry(cell).or_insert(0) += 1;
+    }
+    pub fn remove(&mut self, cell: (i32, i32)) {
+        if let Some(count) = self.counts.get_mut(&cell) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&cell);
+            }
+        }
+    }
+    pub fn count(&self, cell: (i32, i32)) -> u32 {
+        self.counts.get(&cell).copied().unwrap_or(0)
+    }
+    pub fn contains(&self, cell: (i32, i32)) -> bool {
+        self.counts.contains_key(&cell)
+    }
+    pub fn cells(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.counts.keys().copied()
+    }
+    pub fn clear(&mut self) {
+        self.counts.clear();
+    }
+}
+// Seconds of simulated time, advanced from Time by advance_game_clock. Headless
+// runs and tests can advance it by hand instead of waiting on the wall clock.
+#[derive(Default)]
+pub struct GameClock {
+    pub seconds: f64,
+}
+// Simulated time not yet spent on steps. The clock runs into it every frame and each
+// step takes its own interval back out, so the step rate holds however the frames fall.
+pub struct StepAccumulator {
+    pub seconds: f64,
+    // The clock reading it has been filled up to
+    pub filled_to: f64,
+}
+impl StepAccumulator {
+    pub fn new(now: f64) -> Self {
+        StepAccumulator {
+            seconds: 0.,
+            filled_to: now,
+        }
+    }
+    // Adds the time since the last fill. After a long stall at most MAX_STEPS_PER_FRAME
+    // steps are kept, the rest of the stall is dropped rather than fast-forwarded.
+    pub fn fill(&mut self, now: f64, interval: f64) {
+        self.seconds += now - self.filled_to;
+        self.filled_to = now;
+        self.seconds = self.seconds.min(interval * MAX_STEPS_PER_FRAME as f64);
+    }
+    // Moves the clock reading along without adding the time, for while the game isn't running
+    pub fn skip(&mut self, now: f64) {
+        self.filled_to = now;
+    }
+    // Whole steps of `interval` that fit in the accumulated time
+    pub fn steps_due(&self, interval: f64) -> u32 {
+        (self.seconds / interval).floor() as u32
+    }
+    // Brings the next step forward to now, as long as EARLY_STEP_FRACTION of it has passed.
+    // The step after it then waits a full interval again.
+    pub fn hurry(&mut self, interval: f64) {
+        if self.seconds >= interval * EARLY_STEP_FRACTION {
+            self.seconds = self.seconds.max(interval);
+        }
+    }
+    pub fn take_step(&mut self, interval: f64) {
+        self.seconds -= interval;
+    }
+    // How far through the next step the accumulated time is, between 0 and 1
+    pub fn fraction(&self, interval: f64) -> f64 {
+        (self.seconds / interval).clamp(0., 1.)
+    }
+}
+// Developer controls over the step rate, only driven by keys with the debug-controls
+// feature. Slow motion stretches every step; `step_once` runs a single step of a paused
+// game and is cleared as soon as it has.
+#[derive(Default)]
+pub struct DebugStep {
+    pub slow_motion: bool,
+    pub step_once: bool,
+}
+impl DebugStep {
+    // The step interval to run at, `seconds` being the game's own
+    pub fn scaled(&self, seconds: f64) -> f64 {
+        if self.slow_motion {
+            seconds * SLOW_MOTION_FACTOR
+        } else {
+            seconds
+        }
+    }
+}
+// Starts the first snake of every round STRESS_LENGTH segments long, to profile the rules
+// under load. Toggled with the debug-controls feature.
+#[derive(Default)]
+pub struct StressTest(pub bool);
+pub struct Tick {
+    // Whether the step systems are running a step right now
+    pub allowed: bool,
+    // Steps still to run this frame, the running one included. More than one means the
+    // game is catching up after a stall.
+    pub due: u32,
+}
+impl Tick {
+    pub fn new() -> Self {
+        Tick {
+            allowed: true,
+            due: 0,
+        }
+    }
+}
+impl Default for Tick {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// The seed every round starts from, None for a fresh random one each round
+pub struct RngSeed(pub Option<u64>);
+// Every random choice of a round, food placement included, comes from here. Seeded at the
+// start of each round so a recording can play the round again exactly.
+pub struct GameRng {
+    pub seed: u64,
+    pub rng: StdRng,
+}
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+// The way each snake moved on every step of a round, one entry per step holding one
+// direction per player, along with the seed the round was played with. A replay assumes
+// the same options as the recorded round.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub seed: u64,
+    pub moves: Vec<Vec<Direction>>,
+}
+impl Recording {
+    pub fn new(seed: u64) -> Self {
+        Recording {
+            seed,
+            moves: Vec::new(),
+        }
+    }
+    pub fn load(path: &str) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed recording"))
+    }
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+    // A `seed = ` line, then one line per step with a letter for each player's direction
+    pub fn parse(contents: &str) -> Option<Self> {
+        let mut lines = contents.lines();
+        let seed = lines.next()?.strip_prefix("seed =")?.trim().parse().ok()?;
+        let moves = lines
+            .map(|line| {
+                line.split_whitespace()
+                    .map(|letter| match letter {
+                        "U" => Some(Direction::UP),
+                        "D" => Some(Direction::DOWN),
+                        "L" => Some(Direction::LEFT),
+                        "R" => Some(Direction::RIGHT),
+                        "-" => Some(Direction::NONE),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<Direction>>>()
+            })
+            .collect::<Option<Vec<Vec<Direction>>>>()?;
+        Some(Recording { seed, moves })
+    }
+    pub fn to_text(&self) -> String {
+        let mut text = format!("seed = {}\n", self.seed);
+        for step in &self.moves {
+            let letters: Vec<&str> = step
+                .iter()
+                .map(|direction| match direction {
+                    Direction::UP => "U",
+                    Direction::DOWN => "D",
+                    Direction::LEFT => "L",
+                    Direction::RIGHT => "R",
+                    Direction::NONE => "-",
+                })
+                .collect();
+            text.push_str(&letters.join(" "));
+            text.push('\n');
+        }
+        text
+    }
+}
+// A recording played back in place of the players' input, None while playing normally
+pub struct Playback {
+    pub recording: Option<Recording>,
+    // The next step of the recording to play
+    pub step: usize,
+}
+// Holds the snakes still at the start of a round while 3, 2, 1 counts down
+pub struct Countdown {
+    pub ends_at: f64,
+    // Whether the snakes were set off once the countdown ran out
+    pub started: bool,
+}
+impl Countdown {
+    // A zero length skips the countdown, the snakes then wait for the first turn as before
+    pub fn new(now: f64, seconds: f64) -> Self {
+        Countdown {
+            ends_at: now + seconds,
+            started: seconds <= 0.,
+        }
+    }
+    pub fn running(&self, now: f64) -> bool {
+        now < self.ends_at
+    }
+    // What the overlay shows: the whole seconds left, then "Go!" for a moment
+    pub fn label(&self, now: f64) -> Option<String> {
+        let left = self.ends_at - now;
+        if left > 0. {
+            Some(left.ceil().to_string())
+        } else if left > -GO_SECONDS && self.started {
+            Some("Go!".to_string())
+        } else {
+            None
+        }
+    }
+}
+// Seconds counted down before each round, 0 for none
+pub struct CountdownLength(pub f64);
+// How long a time attack round lasts, None for the regular mode without a limit. Chosen
+// in the menu.
+pub struct TimeLimit(pub Option<f64>);
+// The dashes asked for on the next step, each one taking the head two cells at once, and
+// when on the game clock each player can dash again
+#[derive(Default)]
+pub struct Dash {
+    pub requested: HashSet<Player>,
+    pub ready_at: HashMap<Player, f64>,
+}
+impl Dash {
+    pub fn remaining(&self, player: Player, now: f64) -> f64 {
+        self.ready_at
+            .get(&player)
+            .map_or(0., |ready_at| (ready_at - now).max(0.))
+    }
+    pub fn ready(&self, player: Player, now: f64) -> bool {
+        self.remaining(player, now) == 0.
+    }
+    // "ready", or the whole seconds left to wait
+    pub fn label(&self, player: Player, now: f64) -> String {
+        match self.remaining(player, now) {
+            remaining if remaining > 0. => format!("{}s", remaining.ceil()),
+            _ => "ready".to_string(),
+        }
+    }
+}
+// Off in practice mode: nothing ends the round, the snake goes through walls and itself
+// and across the edges of the board. Chosen in the menu.
+pub struct CollisionsEnabled(pub bool);
+// Foods to eat for a win, 0 for the regular mode without one. Chosen in the menu, while
+// `eaten` counts the foods of the round so far, golden ones too.
+pub struct Objective {
+    pub target: u32,
+    pub eaten: u32,
+}
+impl Objective {
+    pub fn new(target: u32) -> Self {
+        Objective { target, eaten: 0 }
+    }
+    pub fn reached(&self) -> bool {
+        self.target > 0 && self.eaten >= self.target
+    }
+    pub fn label(&self) -> Option<String> {
+        match self.target {
+            0 => None,
+            target => Some(format!("{}/{}", self.eaten, target)),
+        }
+    }
+}
+// What's left of the round's time limit, None in the regular mode. Only the time the
+// round is actually played counts, not the countdown or a pause.
+pub struct TimeRemaining {
+    pub seconds: Option<f64>,
+    // The clock reading it has been counted down to
+    pub counted_to: f64,
+}
+impl TimeRemaining {
+    pub fn new(limit: Option<f64>, now: f64) -> Self {
+        TimeRemaining {
+            seconds: limit,
+            counted_to: now,
+        }
+    }
+    // Takes the time since the last count off while `running`, otherwise only keeps up
+    // with the clock
+    pub fn count(&mut self, now: f64, running: bool) {
+        if let Some(seconds) = self.seconds.filter(|_| running) {
+            self.seconds = Some((seconds - (now - self.counted_to)).max(0.));
+        }
+        self.counted_to = now;
+    }
+    pub fn is_up(&self) -> bool {
+        self.seconds == Some(0.)
+    }
+    // The whole seconds left, rounded up so the last second still shows as 1
+    pub fn label(&self) -> Option<String> {
+        self.seconds.map(|seconds| seconds.ceil().to_string())
+    }
+}
+pub struct Sounds {
+    pub eat: Handle<AudioSource>,
+    pub crash: Handle<AudioSource>,
+}
+pub struct Muted(pub bool);
+pub struct Score {
+    pub value: u32,
+}
+// Foods eaten in quick succession. Each one eaten within COMBO_WINDOW_TICKS of the one
+// before raises the multiplier on its points; letting the window run out resets it.
+#[derive(Debug, PartialEq)]
+pub struct Combo {
+    pub multiplier: u32,
+    pub ticks_left: u32,
+}
+impl Combo {
+    pub fn new() -> Self {
+        Combo {
+            multiplier: 1,
+            ticks_left: 0,
+        }
+    }
+    // Called for every food eaten, before its points are counted
+    pub fn eat(&mut self) {
+        if self.ticks_left > 0 {
+            self.multiplier = (self.multiplier + 1).min(COMBO_MAX_MULTIPLIER);
+        }
+        self.ticks_left = COMBO_WINDOW_TICKS;
+    }
+    pub fn tick(&mut self) {
+        self.ticks_left = self.ticks_left.saturating_sub(1);
+        if self.ticks_left == 0 {
+            self.multiplier = 1;
+        }
+    }
+}
+impl Default for Combo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+pub struct HighScore {
+    pub value: u32,
+}
+impl HighScore {
+    // A missing or unreadable file just means no high score yet
+    pub fn load(path: &str) -> Self {
+        let value = fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| contents.trim().parse().ok())
+            .unwrap_or(0);
+        HighScore { value }
+    }
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.value.to_string())
+    }
+}
+pub struct FoodDecay(pub bool);
+// Drops a second quick turn that would take the head straight back alongside its neck
+pub struct UTurnGuard(pub bool);
+// A queued turn steps the snake right away instead of on the next regular step
+pub struct EarlyTurns(pub bool);
+// The way the first snake is already moving when a round starts, the second one heads the
+// opposite way. NONE keeps the snakes still until the first turn.
+pub struct StartDirection(pub Direction);
+// Segments the snake starts a round with, the head included
+pub struct StartLength(pub usize);
+// The most segments a snake grows to, the head included. Food eaten at the cap still
+// scores. None lets it grow for good.
+pub struct MaxLength(pub Option<usize>);
+// Snakes on the board, one per player. Chosen in the menu, 1 or 2.
+pub struct PlayerCount(pub u8);
+// The computer steers the second snake, adding one against a single player
+pub struct AiEnabled(pub bool);
+// Foods on the board at once, each on its own cell
+pub struct FoodCount(pub usize);
+// Odds of a golden food showing up each time a regular one is eaten
+pub struct GoldenFoodChance(pub f64);
+// Odds of a rotten food showing up each time a regular one is eaten
+pub struct RottenFoodChance(pub f64);
+pub struct Paused(pub bool);
+// Whether the background checkerboard is drawn
+pub struct ShowGrid(pub bool);
+// Whether the FPS and step rate overlay is shown
+pub struct ShowDiagnostics(pub bool);
+// Whether the overlay listing the cells of the snakes and foods is shown
+pub struct ShowCellOverlay(pub bool);
+// Every color on the board. The snake body runs from `body_near` right behind the head
+// to `body_far` at the tip of the tail, food fades from `food` to `food_stale` as its
+// value drops. A head that just ate lights up in `flash`, the snakes of a lost round
+// turn `dying` as they fade away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub head: Color,
+    pub eyes: Color,
+    pub flash: Color,
+    pub dying: Color,
+    pub body_near: Color,
+    pub body_far: Color,
+    pub food: Color,
+    pub food_stale: Color,
+    pub golden_food: Color,
+    pub rotten_food: Color,
+    pub wall: Color,
+    pub portal: Color,
+    // Clear color of the window, showing around the board
+    pub background: Color,
+    // The two shades the background cells alternate between
+    pub grid: [Color; 2],
+    // The second player's snake, shaded the same way as the first
+    pub rival_head: Color,
+    pub rival_body_near: Color,
+    pub rival_body_far: Color,
+}
+impl Theme {
+    pub fn classic() -> Self {
+        Theme {
+            name: "classic",
+            head: Color::rgb(1., 1., 1.),
+            eyes: Color::rgb(0., 0., 0.),
+            flash: Color::rgb(1., 1., 0.5),
+            dying: Color::rgb(0.9, 0.1, 0.1),
+            body_near: Color::rgb(0.75, 0.75, 0.75),
+            body_far: Color::rgb(0.35, 0.35, 0.35),
+            food: Color::rgb(1., 0., 0.),
+            food_stale: Color::rgb(0.3, 0., 0.),
+            golden_food: Color::rgb(1., 0.84, 0.),
+            rotten_food: Color::rgb(0.45, 0.6, 0.1),
+            wall: Color::rgb(0.5, 0.5, 0.5),
+            portal: Color::rgb(0.6, 0.3, 1.),
+            background: Color::rgb(0.04, 0.04, 0.04),
+            grid: [Color::rgb(0.1, 0.1, 0.1), Color::rgb(0.13, 0.13, 0.13)],
+            rival_head: Color::rgb(1., 0.85, 0.6),
+            rival_body_near: Color::rgb(0.9, 0.6, 0.3),
+            rival_body_far: Color::rgb(0.45, 0.25, 0.1),
+        }
+    }
+    pub fn forest() -> Self {
+        Theme {
+            name: "forest",
+            head: Color::rgb(0.8, 1., 0.3),
+            eyes: Color::rgb(0.1, 0.05, 0.),
+            flash: Color::rgb(1., 1., 0.85),
+            dying: Color::rgb(1., 0.3, 0.3),
+            body_near: Color::rgb(0.2, 0.8, 0.2),
+            body_far: Color::rgb(0.05, 0.35, 0.1),
+            food: Color::rgb(1., 0.3, 0.3),
+            food_stale: Color::rgb(0.4, 0.15, 0.1),
+            golden_food: Color::rgb(1., 0.84, 0.),
+            rotten_food: Color::rgb(0.5, 0.35, 0.6),
+            wall: Color::rgb(0.4, 0.27, 0.13),
+            portal: Color::rgb(0.3, 0.9, 0.9),
+            background: Color::rgb(0.03, 0.06, 0.03),
+            grid: [Color::rgb(0.08, 0.14, 0.07), Color::rgb(0.1, 0.17, 0.09)],
+            rival_head: Color::rgb(1., 0.9, 0.4),
+            rival_body_near: Color::rgb(0.85, 0.6, 0.1),
+            rival_body_far: Color::rgb(0.4, 0.25, 0.05),
+        }
+    }
+    pub fn ocean() -> Self {
+        Theme {
+            name: "ocean",
+            head: Color::rgb(0.6, 1., 1.),
+            eyes: Color::rgb(0., 0.1, 0.2),
+            flash: Color::rgb(1., 1., 1.),
+            dying: Color::rgb(0.85, 0.15, 0.),
+            body_near: Color::rgb(0.1, 0.6, 0.9),
+            body_far: Color::rgb(0.05, 0.15, 0.5),
+            food: Color::rgb(1., 0.5, 0.3),
+            food_stale: Color::rgb(0.4, 0.2, 0.15),
+            golden_food: Color::rgb(1., 0.9, 0.4),
+            rotten_food: Color::rgb(0.5, 0.55, 0.1),
+            wall: Color::rgb(0.55, 0.6, 0.65),
+            portal: Color::rgb(0.9, 0.4, 1.),
+            background: Color::rgb(0.01, 0.03, 0.07),
+            grid: [Color::rgb(0.03, 0.08, 0.15), Color::rgb(0.05, 0.1, 0.19)],
+            rival_head: Color::rgb(1., 0.7, 0.85),
+            rival_body_near: Color::rgb(0.85, 0.3, 0.55),
+            rival_body_far: Color::rgb(0.4, 0.1, 0.3),
+        }
+    }
+    // The built-in themes in the order they are cycled through
+    pub fn presets() -> [Theme; 3] {
+        [Self::classic(), Self::forest(), Self::ocean()]
+    }
+    pub fn named(name: &str) -> Option<Self> {
+        Self::presets().into_iter().find(|theme| theme.name == name)
+    }
+    // The preset after this one, wrapping around to the first
+    pub fn next(&self) -> Self {
+        let presets = Self::presets();
+        let index = presets
+            .iter()
+            .position(|theme| theme.name == self.name)
+            .map_or(0, |index| (index + 1) % presets.len());
+        presets[index].clone()
+    }
+    // Color of the segment at `index` in a snake of `length` segments, the head at 0
+    pub fn segment_color(&self, index: usize, length: usize) -> Color {
+        if index == 0 {
+            return self.head;
+        }
+        let fraction = if length > 2 {
+            (index - 1) as f32 / (length - 2) as f32
+        } else {
+            0.
+        };
+        // The tail end takes body_far as is, the blend would be a rounding error off
+        if fraction >= 1. {
+            return self.body_far;
+        }
+        blend(self.body_near, self.body_far, fraction)
+    }
+    // Like segment_color, in the colors of `player`'s snake
+    pub fn player_segment_color(&self, player: Player, index: usize, length: usize) -> Color {
+        if player.0 == 0 {
+            return self.segment_color(index, length);
+        }
+        let rival = Theme {
+            head: self.rival_head,
+            body_near: self.rival_body_near,
+            body_far: self.rival_body_far,
+            ..self.clone()
+        };
+        rival.segment_color(index, length)
+    }
+    // Color of a food with `worth` left between 0, stale, and 1, fresh
+    pub fn food_color(&self, worth: f32) -> Color {
+        blend(self.food_stale, self.food, worth)
+    }
+    // `own` lit up by the flash, fully at 1 and not at all at 0
+    pub fn flashed(&self, own: Color, flash: f32) -> Color {
+        blend(own, self.flash, flash)
+    }
+}
+impl Default for Theme {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+fn blend(from: Color, to: Color, fraction: f32) -> Color {
+    let from = Vec4::from(from.as_rgba_f32());
+    let to = Vec4::from(to.as_rgba_f32());
+    Color::from(from.lerp(to, fraction.clamp(0., 1.)))
+}
+// Time left on the start-up splash
+pub struct SplashTimer(pub Timer);
+// Time left on the death animation of a lost round. A restart waits until it's gone.
+pub struct DyingTimer(pub Timer);
+// The camera jolt after a crash, up to `magnitude` world units off center and easing off
+// until the timer runs out
+pub struct ScreenShake {
+    pub timer: Timer,
+    pub magnitude: f32,
+}
+// Slide the snake between cells instead of jumping a whole cell each tick
+pub struct SmoothMotion(pub bool);
+pub struct StepInterval {
+    pub seconds: f64,
+    pub floor: f64,
+}
+impl StepInterval {
+    pub fn new() -> Self {
+        StepInterval {
+            seconds: TIME_STEP as f64,
+            floor: STEP_INTERVAL_FLOOR,
+        }
+    }
+    // Each eaten food shortens the step, never going below `floor`
+    pub fn speed_up(&mut self) {
+        self.seconds = (self.seconds * STEP_INTERVAL_FACTOR).max(self.floor);
+    }
+}
+impl Default for StepInterval {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// The step interval a round starts with, before eating speeds it up
+pub struct StartInterval(pub f64);
+// Keys that can be bound in the settings file, written there by their KeyCode name
+const BINDABLE_KEYS: [KeyCode; 50] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Space,
+    KeyCode::Return,
+    KeyCode::Escape,
+    KeyCode::Tab,
+    KeyCode::Back,
+    KeyCode::Insert,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+];
+fn key_code(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS
+        .iter()
+        .copied()
+        .find(|key| format!("{:?}", key) == name)
+}
+// The keys for each action. The turns are the first then the second player's, a player
+// on their own steers with both. Escape pauses and Enter restarts whatever the bindings,
+// being the keys that work the menus too.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyBindings {
+    pub up: [KeyCode; 2],
+    pub down: [KeyCode; 2],
+    pub left: [KeyCode; 2],
+    pub right: [KeyCode; 2],
+    pub dash: [KeyCode; 2],
+    pub pause: KeyCode,
+    pub restart: KeyCode,
+}
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            up: [KeyCode::W, KeyCode::Up],
+            down: [KeyCode::S, KeyCode::Down],
+            left: [KeyCode::A, KeyCode::Left],
+            right: [KeyCode::D, KeyCode::Right],
+            dash: [KeyCode::LShift, KeyCode::RShift],
+            pause: KeyCode::P,
+            restart: KeyCode::Space,
+        }
+    }
+}
+impl KeyBindings {
+    // Every binding under its name in the settings file
+    pub fn named_keys(&mut self) -> [(&'static str, &mut KeyCode); 12] {
+        let [up, rival_up] = &mut self.up;
+        let [down, rival_down] = &mut self.down;
+        let [left, rival_left] = &mut self.left;
+        let [right, rival_right] = &mut self.right;
+        let [dash, rival_dash] = &mut self.dash;
+        [
+            ("up_key", up),
+            ("down_key", down),
+            ("left_key", left),
+            ("right_key", right),
+            ("dash_key", dash),
+            ("rival_up_key", rival_up),
+            ("rival_down_key", rival_down),
+            ("rival_left_key", rival_left),
+            ("rival_right_key", rival_right),
+            ("rival_dash_key", rival_dash),
+            ("pause_key", &mut self.pause),
+            ("restart_key", &mut self.restart),
+        ]
+    }
+}
+// What the options screen edits, plus the theme and mute toggles, kept on disk between
+// runs. It's written into the resources the rules read when a round is started from the menu.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    // Index into SPEED_INTERVALS
+    pub speed: usize,
+    pub board: Board,
+    pub boundary_mode: BoundaryMode,
+    pub self_collision_mode: SelfCollisionMode,
+    pub food_count: usize,
+    pub scoring_mode: ScoringMode,
+    // Name of one of the Theme presets
+    pub theme: &'static str,
+    pub muted: bool,
+    // Whether the window can be dragged to another size, fixed unless the file says so
+    pub resizable: bool,
+    pub view_mode: ViewMode,
+    // Plays every round from the same seed, None for a fresh one each round
+    pub seed: Option<u64>,
+    pub keys: KeyBindings,
+}
+impl Settings {
+    pub fn new(board: Board) -> Self {
+        Settings {
+            speed: 2,
+            board,
+            boundary_mode: BoundaryMode::Wall,
+            self_collision_mode: SelfCollisionMode::Die,
+            food_count: FOOD_COUNT,
+            scoring_mode: ScoringMode::Points,
+            theme: Theme::default().name,
+            muted: false,
+            resizable: false,
+            view_mode: ViewMode::Fit,
+            seed: None,
+            keys: KeyBindings::default(),
+        }
+    }
+    // A missing file gives `defaults`, and so does any line that can't be read, one
+    // setting at a time
+    pub fn load(path: &str, defaults: Settings) -> Self {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents, defaults),
+            Err(_) => defaults,
+        }
+    }
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_config())
+    }
+    // `key = value` lines, a small subset of TOML
+    pub fn parse(contents: &str, defaults: Settings) -> Self {
+        let mut settings = defaults;
+        let side = |value: &str| {
+            value
+                .parse()
+                .ok()
+                .filter(|side| (MIN_BOARD_SIDE..=MAX_BOARD_SIDE).contains(side))
+        };
+        for line in contents.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim().trim_matches('"')),
+                None => continue,
+            };
+            match key {
+                "speed" => {
+                    if let Some(speed) = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|speed| (1..=SPEED_INTERVALS.len()).contains(speed))
+                    {
+                        settings.speed = speed - 1;
+                    }
+                }
+                "columns" => settings.board.cols = side(value).unwrap_or(settings.board.cols),
+                "rows" => settings.board.rows = side(value).unwrap_or(settings.board.rows),
+                "boundary" => match value {
+                    "wall" => settings.boundary_mode = BoundaryMode::Wall,
+                    "wrap" => settings.boundary_mode = BoundaryMode::Wrap,
+                    _ => {}
+                },
+                "self_collision" => match value {
+                    "die" => settings.self_collision_mode = SelfCollisionMode::Die,
+                    "truncate" => settings.self_collision_mode = SelfCollisionMode::Truncate,
+                    _ => {}
+                },
+                "food" => {
+                    if let Some(food_count) = value
+                        .parse()
+                        .ok()
+                        .filter(|food_count| (1..=MAX_FOOD_COUNT).contains(food_count))
+                    {
+                        settings.food_count = food_count;
+                    }
+                }
+                "scoring" => match value {
+                    "points" => settings.scoring_mode = ScoringMode::Points,
+                    "length" => settings.scoring_mode = ScoringMode::Length,
+                    _ => {}
+                },
+                "theme" => {
+                    if let Some(theme) = Theme::named(value) {
+                        settings.theme = theme.name;
+                    }
+                }
+                "muted" => settings.muted = value.parse().unwrap_or(settings.muted),
+                "resizable" => settings.resizable = value.parse().unwrap_or(settings.resizable),
+                "view" => match value {
+                    "fit" => settings.view_mode = ViewMode::Fit,
+                    "pixels" => settings.view_mode = ViewMode::Pixels,
+                    _ => {}
+                },
+                "seed" => settings.seed = value.parse().ok().or(settings.seed),
+                _ => {
+                    let bound = settings
+                        .keys
+                        .named_keys()
+                        .into_iter()
+                        .find(|(name, _)| *name == key);
+                    if let (Some((_, slot)), Some(code)) = (bound, key_code(value)) {
+                        *slot = code;
+                    }
+                }
+            }
+        }
+        settings
+    }
+    pub fn to_config(&self) -> String {
+        let boundary = match self.boundary_mode {
+            BoundaryMode::Wall => "wall",
+            BoundaryMode::Wrap => "wrap",
+        };
+        let self_collision = match self.self_collision_mode {
+            SelfCollisionMode::Die => "die",
+            SelfCollisionMode::Truncate => "truncate",
+        };
+        let scoring = match self.scoring_mode {
+            ScoringMode::Points => "points",
+            ScoringMode::Length => "length",
+        };
+        let view = match self.view_mode {
+            ViewMode::Fit => "fit",
+            ViewMode::Pixels => "pixels",
+        };
+        let mut config = format!(
+            "speed = {}\ncolumns = {}\nrows = {}\nboundary = \"{}\"\nself_collision = \"{}\"\nfood = {}\nscoring = \"{}\"\ntheme = \"{}\"\nmuted = {}\nresizable = {}\nview = \"{}\"\n",
+            self.speed + 1,
+            self.board.cols,
+            self.board.rows,
+            boundary,
+            self_collision,
+            self.food_count,
+            scoring,
+            self.theme,
+            self.muted,
+            self.resizable,
+            view
+        );
+        if let Some(seed) = self.seed {
+            config.push_str(&format!("seed = {}\n", seed));
+        }
+        let mut keys = self.keys;
+        for (name, key) in keys.named_keys() {
+            config.push_str(&format!("{} = \"{:?}\"\n", name, key));
+        }
+        config
+    }
+    pub fn step_interval(&self) -> f64 {
+        SPEED_INTERVALS[self.speed]
+    }
+    // Moves a field one notch up or down, stopping at its limits
+    pub fn adjust(&mut self, field: OptionField, up: bool) {
+        let step = |value: usize, min: usize, max: usize| {
+            if up {
+                (value + 1).min(max)
+            } else {
+                value.saturating_sub(1).max(min)
+            }
+        };
+        let (min_side, max_side) = (MIN_BOARD_SIDE as usize, MAX_BOARD_SIDE as usize);
+        match field {
+            OptionField::Speed => self.speed = step(self.speed, 0, SPEED_INTERVALS.len() - 1),
+            OptionField::Columns => {
+                self.board.cols = step(self.board.cols as usize, min_side, max_side) as u32
+            }
+            OptionField::Rows => {
+                self.board.rows = step(self.board.rows as usize, min_side, max_side) as u32
+            }
+            OptionField::Boundary => {
+                self.boundary_mode = match self.boundary_mode {
+                    BoundaryMode::Wall => BoundaryMode::Wrap,
+                    BoundaryMode::Wrap => BoundaryMode::Wall,
+                }
+            }
+            OptionField::SelfCollision => {
+                self.self_collision_mode = match self.self_collision_mode {
+                    SelfCollisionMode::Die => SelfCollisionMode::Truncate,
+                    SelfCollisionMode::Truncate => SelfCollisionMode::Die,
+                }
+            }
+            OptionField::Food => self.food_count = step(self.food_count, 1, MAX_FOOD_COUNT),
+            OptionField::Scoring => {
+                self.scoring_mode = match self.scoring_mode {
+                    ScoringMode::Points => ScoringMode::Length,
+                    ScoringMode::Length => ScoringMode::Points,
+                }
+            }
+        }
+    }
+    pub fn label(&self, field: OptionField) -> String {
+        match field {
+            OptionField::Speed => format!("Speed: {}", self.speed + 1),
+            OptionField::Columns => format!("Board width: {}", self.board.cols),
+            OptionField::Rows => format!("Board height: {}", self.board.rows),
+            OptionField::Boundary => format!("Edges: {:?}", self.boundary_mode),
+            OptionField::SelfCollision => {
+                format!("Biting yourself: {:?}", self.self_collision_mode)
+            }
+            OptionField::Food => format!("Food: {}", self.food_count),
+            OptionField::Scoring => format!("Score by: {:?}", self.scoring_mode),
+        }
+    }
+}
+// Cells may be wider than they are tall or the other way around, they're GRID_SIZE
+// square by default
+pub struct GridConfig {
+    pub cell_w: f32,
+    pub cell_h: f32,
+}
+impl GridConfig {
+    pub fn new() -> Self {
+        GridConfig {
+            cell_w: GRID_SIZE,
+            cell_h: GRID_SIZE,
+        }
+    }
+    pub fn cell_size(&self) -> Vec2 {
+        Vec2::new(self.cell_w, self.cell_h)
+    }
+    // How far one step going `direction` moves across the world
+    pub fn step_length(&self, direction: Direction) -> f32 {
+        match direction {
+            Direction::LEFT | Direction::RIGHT => self.cell_w,
+            Direction::UP | Direction::DOWN => self.cell_h,
+            Direction::NONE => 0.,
+        }
+    }
+    // The square sprites on a cell are sized from its shorter side, so they fit it either way
+    pub fn shorter_side(&self) -> f32 {
+        self.cell_w.min(self.cell_h)
+    }
+    pub fn head_size(&self) -> f32 {
+        self.shorter_side() * HEAD_SCALE
+    }
+    pub fn tail_size(&self) -> f32 {
+        self.shorter_side() * TAIL_SCALE
+    }
+    // The last segment is drawn narrower, so the tail tapers off
+    pub fn tail_tip_size(&self) -> f32 {
+        self.shorter_side() * TAIL_TIP_SCALE
+    }
+    pub fn column_count(&self, width: f32) -> i32 {
+        whole_cells(width, self.cell_w)
+    }
+    pub fn row_count(&self, height: f32) -> i32 {
+        whole_cells(height, self.cell_h)
+    }
+    // World position of the center of a cell. Cells count from the bottom left of the
+    // board, which is centered on the origin, so every entity shares one lattice.
+    pub fn grid_to_world(&self, cell_x: i32, cell_y: i32, board: &Board) -> Vec2 {
+        Vec2::new(
+            cell_to_coordinate(cell_x, board.cols, self.cell_w),
+            cell_to_coordinate(cell_y, board.rows, self.cell_h),
+        )
+    }
+    pub fn world_to_grid(&self, position: Vec3, board: &Board) -> (i32, i32) {
+        (
+            coordinate_to_cell(position.x, board.cols, self.cell_w),
+            coordinate_to_cell(position.y, board.rows, self.cell_h),
+        )
+    }
+    // The cell the head starts on, the middle of the board
+    pub fn spawn_cell(&self, board: &Board) -> (i32, i32) {
+        (board.cols as i32 / 2, board.rows as i32 / 2)
+    }
+}
+// Number of whole cells that fit along a window side. Floored explicitly, a part of a
+// cell at the edge doesn't count, and even a window narrower than one cell gets one.
+fn whole_cells(window_length: f32, cell_length: f32) -> i32 {
+    (window_length / cell_length).floor().max(1.) as i32
+}
+fn cell_to_coordinate(cell: i32, board_cells: u32, cell_length: f32) -> f32 {
+    (cell as f32 - board_cells as f32 / 2.) * cell_length + cell_length / 2.
+}
+fn coordinate_to_cell(value: f32, board_cells: u32, cell_length: f32) -> i32 {
+    ((value - cell_length / 2.) / cell_length + board_cells as f32 / 2.).round() as i32
+}
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// */Resources
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn occupied_cells_count_overlaps() {
+        let mut occupied = OccupiedCells::default();
+        occupied.add((1, 2));
+        occupied.add((1, 2));
+        occupied.add((3, 4));
+        assert_eq!(occupied.count((1, 2)), 2);
+        assert!(occupied.contains((3, 4)));
+
+        // Leaving a shared cell keeps it taken by what's still on it
+        occupied.remove((1, 2));
+        assert_eq!(occupied.count((1, 2)), 1);
+        occupied.remove((1, 2));
+        assert!(!occupied.contains((1, 2)));
+        // Removing a free cell leaves nothing behind
+        occupied.remove((5, 5));
+        assert_eq!(occupied.cells().collect::<Vec<_>>(), vec![(3, 4)]);
+
+        occupied.clear();
+        assert_eq!(occupied.count((3, 4)), 0);
+    }
+
+    #[test]
+    fn settings_adjust_within_limits() {
+        let mut settings = Settings::new(Board {
+            cols: MIN_BOARD_SIDE,
+            rows: MAX_BOARD_SIDE,
+        });
+        settings.adjust(OptionField::Columns, false);
+        settings.adjust(OptionField::Rows, true);
+        assert_eq!(
+            settings.board,
+            Board {
+                cols: MIN_BOARD_SIDE,
+                rows: MAX_BOARD_SIDE,
+            }
+        );
+        settings.adjust(OptionField::Columns, true);
+        assert_eq!(settings.board.cols, MIN_BOARD_SIDE + 1);
+
+        for _ in 0..SPEED_INTERVALS.len() {
+            settings.adjust(OptionField::Speed, true);
+        }
+        assert_eq!(
+            settings.step_interval(),
+            SPEED_INTERVALS[SPEED_INTERVALS.len() - 1]
+        );
+
+        settings.adjust(OptionField::Food, false);
+        assert_eq!(settings.food_count, 1);
+
+        settings.adjust(OptionField::Boundary, true);
+        assert_eq!(settings.boundary_mode, BoundaryMode::Wrap);
+        assert_eq!(settings.label(OptionField::Boundary), "Edges: Wrap");
+        settings.adjust(OptionField::Boundary, false);
+        assert_eq!(settings.boundary_mode, BoundaryMode::Wall);
+
+        settings.adjust(OptionField::SelfCollision, true);
+        assert_eq!(settings.self_collision_mode, SelfCollisionMode::Truncate);
+        assert_eq!(
+            settings.label(OptionField::SelfCollision),
+            "Biting yourself: Truncate"
+        );
+    }
+
+    #[test]
+    fn objective_is_reached_at_its_target_only() {
+        let mut objective = Objective::new(0);
+        objective.eaten = 100;
+        assert!(!objective.reached());
+        assert_eq!(objective.label(), None);
+
+        let mut objective = Objective::new(2);
+        objective.eaten = 1;
+        assert!(!objective.reached());
+        assert_eq!(objective.label(), Some("1/2".to_string()));
+        objective.eaten = 2;
+        assert!(objective.reached());
+    }
+
+    #[test]
+    fn combo_builds_within_the_window_and_lapses() {
+        let mut combo = Combo::new();
+        combo.eat();
+        assert_eq!(combo.multiplier, 1);
+        for _ in 1..COMBO_WINDOW_TICKS {
+            combo.tick();
+        }
+        combo.eat();
+        assert_eq!(combo.multiplier, 2);
+        for _ in 0..COMBO_MAX_MULTIPLIER {
+            combo.eat();
+        }
+        assert_eq!(combo.multiplier, COMBO_MAX_MULTIPLIER);
+
+        for _ in 0..COMBO_WINDOW_TICKS {
+            combo.tick();
+        }
+        assert_eq!(combo, Combo::new());
+    }
+
+    #[test]
+    fn time_remaining_counts_down_only_while_running() {
+        let mut time = TimeRemaining::new(Some(60.), 10.);
+        time.count(12.5, true);
+        assert_eq!(time.seconds, Some(57.5));
+        assert_eq!(time.label(), Some("58".to_string()));
+
+        // Paused for a while, then played on
+        time.count(100., false);
+        time.count(101., true);
+        assert_eq!(time.seconds, Some(56.5));
+
+        time.count(200., true);
+        assert!(time.is_up());
+        assert_eq!(time.label(), Some("0".to_string()));
+
+        let mut unlimited = TimeRemaining::new(None, 0.);
+        unlimited.count(1000., true);
+        assert!(!unlimited.is_up());
+        assert_eq!(unlimited.label(), None);
+    }
+
+    #[test]
+    fn countdown_counts_whole_seconds_then_says_go() {
+        let mut countdown = Countdown::new(10., 3.);
+        assert!(countdown.running(10.));
+        assert_eq!(countdown.label(10.), Some("3".to_string()));
+        assert_eq!(countdown.label(11.5), Some("2".to_string()));
+        assert_eq!(countdown.label(12.9), Some("1".to_string()));
+        assert!(!countdown.running(13.));
+
+        countdown.started = true;
+        assert_eq!(countdown.label(13.2), Some("Go!".to_string()));
+        assert_eq!(countdown.label(13.5), None);
+
+        let skipped = Countdown::new(10., 0.);
+        assert!(!skipped.running(10.) && skipped.started);
+    }
+
+    #[test]
+    fn grid_coordinates_round_trip() {
+        let grid_config = GridConfig::new();
+        let board = Board { cols: 16, rows: 12 };
+        for x in 0..16 {
+            for y in 0..12 {
+                let position = grid_config.grid_to_world(x, y, &board);
+                assert_eq!(
+                    grid_config.world_to_grid(position.extend(0.), &board),
+                    (x, y)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_food_cell_is_reachable_from_the_head_spawn() {
+        let grid_config = GridConfig::new();
+        for (cols, rows) in [(16, 12), (15, 11), (15, 12), (2, 1)] {
+            let board = Board { cols, rows };
+            let (spawn_x, spawn_y) = grid_config.spawn_cell(&board);
+            let head = grid_config.grid_to_world(spawn_x, spawn_y, &board);
+            for x in 0..cols as i32 {
+                for y in 0..rows as i32 {
+                    let steps =
+                        (grid_config.grid_to_world(x, y, &board) - head) / grid_config.cell_size();
+                    assert_eq!(
+                        steps,
+                        steps.round(),
+                        "{}x{} cell ({}, {})",
+                        cols,
+                        rows,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_crosses_to_opposite_edge() {
+        let board = Board { cols: 16, rows: 12 };
+        assert_eq!(board.wrap(GridPos::new(16, 5)), GridPos::new(0, 5));
+        assert_eq!(board.wrap(GridPos::new(-1, 5)), GridPos::new(15, 5));
+        assert_eq!(board.wrap(GridPos::new(15, 5)), GridPos::new(15, 5));
+        assert_eq!(board.wrap(GridPos::new(3, 12)), GridPos::new(3, 0));
+        assert_eq!(board.wrap(GridPos::new(3, -1)), GridPos::new(3, 11));
+    }
+
+    #[test]
+    fn grid_config_scales_with_cell_size() {
+        let grid_config = GridConfig {
+            cell_w: 20.,
+            cell_h: 20.,
+        };
+        let board = Board::fit(&WinSize { w: 800., h: 600. }, &grid_config, 0.);
+        assert_eq!(board, Board { cols: 40, rows: 30 });
+        assert_eq!(
+            grid_config.grid_to_world(20, 15, &board),
+            Vec2::new(10., 10.)
+        );
+        assert_eq!(grid_config.head_size(), 20. * HEAD_SCALE);
+        assert_eq!(grid_config.tail_size(), 20. * TAIL_SCALE);
+        assert_eq!(grid_config.tail_tip_size(), 20. * TAIL_TIP_SCALE);
+    }
+
+    #[test]
+    fn rectangular_cells_keep_each_axis_apart() {
+        let grid_config = GridConfig {
+            cell_w: 40.,
+            cell_h: 20.,
+        };
+        let board = Board::fit(&WinSize { w: 800., h: 600. }, &grid_config, 0.);
+        assert_eq!(board, Board { cols: 20, rows: 30 });
+        assert_eq!(
+            grid_config.grid_to_world(10, 15, &board),
+            Vec2::new(20., 10.)
+        );
+        assert_eq!(
+            grid_config.world_to_grid(Vec3::new(20., 10., 0.), &board),
+            (10, 15)
+        );
+        assert_eq!(grid_config.step_length(Direction::RIGHT), 40.);
+        assert_eq!(grid_config.step_length(Direction::DOWN), 20.);
+        // Square sprites still fit inside the narrow side of a cell
+        assert_eq!(grid_config.head_size(), 20. * HEAD_SCALE);
+    }
+
+    #[test]
+    fn board_fits_the_window_inside_the_margin() {
+        let grid_config = GridConfig::new();
+        let win_size = WinSize { w: 800., h: 600. };
+        let board = Board::fit(&win_size, &grid_config, 50.);
+        assert_eq!(board, Board { cols: 14, rows: 10 });
+        assert!(board.contains((13, 9)));
+        assert!(!board.contains((14, 0)));
+        assert!(!board.contains((0, -1)));
+
+        let letterboxed = board.win_size(&grid_config, 50.);
+        assert_eq!((letterboxed.w, letterboxed.h), (800., 600.));
+
+        // Hardly a window at all still makes a board to play on
+        let tiny = WinSize { w: 30., h: 0. };
+        assert_eq!(
+            Board::fit(&tiny, &grid_config, 0.),
+            Board {
+                cols: MIN_BOARD_SIDE,
+                rows: MIN_BOARD_SIDE
+            }
+        );
+    }
+
+    #[test]
+    fn theme_shades_the_body_from_head_to_tail() {
+        for theme in [Theme::classic(), Theme::forest(), Theme::ocean()] {
+            assert_eq!(theme.segment_color(0, 5), theme.head);
+            assert_ne!(theme.segment_color(1, 5), theme.head);
+            assert_eq!(theme.segment_color(1, 5), theme.body_near);
+            assert_eq!(theme.segment_color(4, 5), theme.body_far);
+            assert_eq!(theme.segment_color(1, 2), theme.body_near);
+        }
+    }
+
+    #[test]
+    fn themes_cycle_through_every_preset() {
+        let mut theme = Theme::default();
+        let mut names = Vec::new();
+        for _ in 0..Theme::presets().len() {
+            names.push(theme.name);
+            theme = theme.next();
+        }
+        assert_eq!(names, vec!["classic", "forest", "ocean"]);
+        assert_eq!(theme, Theme::default());
+
+        let classic = Theme::classic();
+        assert_eq!(classic.food_color(1.), classic.food);
+        assert_eq!(classic.food_color(0.), classic.food_stale);
+        assert_eq!(classic.flashed(classic.wall, 1.), classic.flash);
+        assert_eq!(classic.flashed(classic.wall, 0.), classic.wall);
+    }
+
+    #[test]
+    fn high_score_round_trips_through_file() {
+        let path = std::env::temp_dir().join("rusnake_high_score_test.txt");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        assert_eq!(HighScore::load(path).value, 0);
+
+        HighScore { value: 42 }.save(path).unwrap();
+        assert_eq!(HighScore::load(path).value, 42);
+
+        fs::write(path, "not a number").unwrap();
+        assert_eq!(HighScore::load(path).value, 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn settings_round_trip_through_config() {
+        let defaults = Settings::new(Board { cols: 16, rows: 12 });
+        let mut settings = defaults;
+        settings.adjust(OptionField::Speed, true);
+        settings.adjust(OptionField::Rows, false);
+        settings.adjust(OptionField::Boundary, true);
+        settings.adjust(OptionField::SelfCollision, true);
+        settings.adjust(OptionField::Scoring, true);
+        settings.theme = "ocean";
+        settings.muted = true;
+        settings.resizable = true;
+        settings.view_mode = ViewMode::Pixels;
+        assert_eq!(Settings::parse(&settings.to_config(), defaults), settings);
+        settings.seed = Some(1234);
+        assert_eq!(Settings::parse(&settings.to_config(), defaults), settings);
+        settings.keys.up = [KeyCode::I, KeyCode::Key8];
+        settings.keys.pause = KeyCode::Tab;
+        assert_eq!(Settings::parse(&settings.to_config(), defaults), settings);
+
+        let path = std::env::temp_dir().join("rusnake_settings_test.toml");
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        assert_eq!(Settings::load(path, defaults), defaults);
+        settings.save(path).unwrap();
+        assert_eq!(Settings::load(path, defaults), settings);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn malformed_settings_fall_back_one_by_one() {
+        let defaults = Settings::new(Board { cols: 16, rows: 12 });
+        let settings = Settings::parse(
+            "speed = 9\ncolumns = 20\nrows = wide\nboundary = \"bounce\"\ntheme = \"forest\"\nnonsense",
+            defaults,
+        );
+        assert_eq!(settings.speed, defaults.speed);
+        assert_eq!(settings.board, Board { cols: 20, rows: 12 });
+        assert_eq!(settings.boundary_mode, BoundaryMode::Wall);
+        assert_eq!(settings.theme, "forest");
+        assert_eq!(Settings::parse("", defaults), defaults);
+
+        let settings = Settings::parse(
+            "left_key = \"J\"\nright_key = \"Mouse\"\nrival_down_key = \"Key2\"\nfire_key = \"F\"",
+            defaults,
+        );
+        assert_eq!(settings.keys.left, [KeyCode::J, KeyCode::Left]);
+        assert_eq!(settings.keys.right, defaults.keys.right);
+        assert_eq!(settings.keys.down, [KeyCode::S, KeyCode::Key2]);
+    }
+
+    #[test]
+    fn recording_round_trips_through_text() {
+        let recording = Recording {
+            seed: 42,
+            moves: vec![
+                vec![Direction::NONE, Direction::LEFT],
+                vec![Direction::UP, Direction::DOWN],
+                vec![Direction::RIGHT, Direction::DOWN],
+            ],
+        };
+        assert_eq!(Recording::parse(&recording.to_text()), Some(recording));
+
+        assert_eq!(Recording::parse("seed = 7\n"), Some(Recording::new(7)));
+        assert_eq!(Recording::parse("seed = 7\nU X\n"), None);
+        assert_eq!(Recording::parse("U\n"), None);
+    }
+
+    #[test]
+    fn step_accumulator_keeps_the_remainder_between_frames() {
+        let mut accumulator = StepAccumulator::new(1.);
+        accumulator.fill(1.375, 0.25);
+        assert_eq!(accumulator.steps_due(0.25), 1);
+        accumulator.take_step(0.25);
+        assert_eq!(accumulator.steps_due(0.25), 0);
+        assert_eq!(accumulator.fraction(0.25), 0.5);
+
+        // The leftover half step counts towards the next frame instead of being lost
+        accumulator.fill(1.5, 0.25);
+        assert_eq!(accumulator.steps_due(0.25), 1);
+    }
+
+    #[test]
+    fn step_accumulator_catches_up_after_a_stall() {
+        let mut accumulator = StepAccumulator::new(0.);
+        accumulator.fill(0.8, 0.25);
+        assert_eq!(accumulator.steps_due(0.25), 3);
+
+        // A paused stretch adds nothing
+        accumulator.skip(10.);
+        accumulator.fill(10., 0.25);
+        assert_eq!(accumulator.steps_due(0.25), 3);
+
+        accumulator.fill(100., 0.25);
+        assert_eq!(accumulator.steps_due(0.25), MAX_STEPS_PER_FRAME);
+    }
+
+    #[test]
+    fn hurrying_only_brings_a_step_forward_past_the_minimum() {
+        let mut accumulator = StepAccumulator::new(0.);
+        accumulator.fill(0.1, 0.25);
+        accumulator.hurry(0.25);
+        assert_eq!(accumulator.steps_due(0.25), 0);
+
+        accumulator.fill(0.15, 0.25);
+        accumulator.hurry(0.25);
+        assert_eq!(accumulator.steps_due(0.25), 1);
+        accumulator.take_step(0.25);
+        assert_eq!(accumulator.fraction(0.25), 0.);
+
+        // Already due steps are left as they are
+        accumulator.fill(0.7, 0.25);
+        accumulator.hurry(0.25);
+        assert_eq!(accumulator.steps_due(0.25), 2);
+    }
+
+    #[test]
+    fn step_interval_shrinks_down_to_floor() {
+        let mut step_interval = StepInterval::new();
+        step_interval.speed_up();
+        assert!(step_interval.seconds < TIME_STEP as f64);
+
+        for _ in 0..1000 {
+            step_interval.speed_up();
+        }
+        assert_eq!(step_interval.seconds, STEP_INTERVAL_FLOOR);
+    }
+
+    #[test]
+    fn harder_difficulty_starts_faster_and_speeds_up_further() {
+        let mut easy = Difficulty::Easy.step_interval(TIME_STEP as f64);
+        let mut normal = Difficulty::Normal.step_interval(TIME_STEP as f64);
+        let mut hard = Difficulty::Hard.step_interval(TIME_STEP as f64);
+        assert_eq!(normal.seconds, TIME_STEP as f64);
+        assert!(easy.seconds > normal.seconds && normal.seconds > hard.seconds);
+
+        for _ in 0..1000 {
+            easy.speed_up();
+            normal.speed_up();
+            hard.speed_up();
+        }
+        assert!(easy.seconds > normal.seconds && normal.seconds > hard.seconds);
+        assert_eq!(hard.seconds, Difficulty::Hard.interval_floor());
+        assert_eq!(Difficulty::Hard.next(), Difficulty::Easy);
+    }
+}